@@ -0,0 +1,77 @@
+//          Copyright Nick G 2023.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! Compares the in place block modes against the versions which allocate a new output
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use cryptopals::block::Aes;
+use cryptopals::{cbc, ecb};
+
+const KEY: &[u8; 16] = b"YELLOW SUBMARINE";
+const IV: &[u8; 16] = b"an initial value";
+const SIZES: [usize; 3] = [64, 4096, 1 << 20];
+
+fn data(size: usize) -> Vec<u8> {
+    (0..size).map(|i| i as u8).collect()
+}
+
+fn ecb_modes(c: &mut Criterion) {
+    let aes = Aes::new_checked(KEY).unwrap();
+    let mut group = c.benchmark_group("ecb");
+    for size in SIZES {
+        let raw = data(size);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_function(format!("copy/{size}"), |b| {
+            b.iter(|| ecb::encrypt_with(&aes, black_box(&raw)).unwrap())
+        });
+        let mut in_place = raw.clone();
+        group.bench_function(format!("in_place/{size}"), |b| {
+            b.iter(|| ecb::encrypt_in_place_with(&aes, black_box(&mut in_place)).unwrap())
+        });
+        group.bench_function(format!("padded_copy/{size}"), |b| {
+            b.iter(|| ecb::encrypt_padded_with(&aes, black_box(&raw)).unwrap())
+        });
+        group.bench_function(format!("padded_in_place/{size}"), |b| {
+            b.iter_batched_ref(
+                || {
+                    let mut spare = Vec::with_capacity(size + 16);
+                    spare.extend_from_slice(&raw);
+                    spare
+                },
+                |spare| ecb::encrypt_padded_in_place_with(&aes, spare).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn cbc_modes(c: &mut Criterion) {
+    let aes = Aes::new_checked(KEY).unwrap();
+    let mut group = c.benchmark_group("cbc");
+    for size in SIZES {
+        let raw = data(size);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_function(format!("copy/{size}"), |b| {
+            b.iter(|| cbc::encrypt_with(&aes, IV, black_box(&raw)).unwrap())
+        });
+        let mut in_place = raw.clone();
+        group.bench_function(format!("in_place/{size}"), |b| {
+            b.iter(|| cbc::encrypt_in_place_with(&aes, IV, black_box(&mut in_place)).unwrap())
+        });
+        let encrypted = cbc::encrypt_with(&aes, IV, &raw).unwrap();
+        group.bench_function(format!("decrypt_copy/{size}"), |b| {
+            b.iter(|| cbc::cbc_decrypt_with(&aes, IV, black_box(&encrypted)).unwrap())
+        });
+        let mut in_place = encrypted.clone();
+        group.bench_function(format!("decrypt_in_place/{size}"), |b| {
+            b.iter(|| cbc::cbc_decrypt_in_place_with(&aes, IV, black_box(&mut in_place)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, ecb_modes, cbc_modes);
+criterion_main!(benches);
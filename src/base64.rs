@@ -4,9 +4,12 @@
 // license that can be found in the LICENSE file or at
 // https://opensource.org/licenses/MIT.
 
-//! Perform base64 encoding bytes. Detail on base64 encoding is
+//! Perform base64 encoding and decoding of bytes. Detail on base64 encoding is
 //! available at https://en.wikipedia.org/wiki/Base64
 
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
 /// Provides the look up of a 6 bit value to the representative base64 value
 const LOOKUP_TABLE: [char; 64] = [
     'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R', 'S',
@@ -20,60 +23,129 @@ const PAD: char = '=';
 /// Mask that only allows the lower 6 bits of a u8
 const SEXTET_MASK: u8 = 0b00111111u8;
 
+/// The maximum line length for MIME encoded base64, RFC 2045
+pub const MIME_LINE_LENGTH: usize = 76;
+
+/// The set of characters used to represent the 6 bit values
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Alphabet {
+    /// The RFC 4648 standard alphabet, using `+` and `/`
+    #[default]
+    Standard,
+    /// The RFC 4648 URL and filename safe alphabet, using `-` and `_`
+    UrlSafe,
+}
+
+impl Alphabet {
+    fn symbol(&self, index: u8) -> char {
+        match (self, index) {
+            (Alphabet::UrlSafe, 62) => '-',
+            (Alphabet::UrlSafe, 63) => '_',
+            _ => LOOKUP_TABLE[index as usize],
+        }
+    }
+
+    fn value(&self, symbol: char) -> Option<u8> {
+        match (self, symbol) {
+            (_, 'A'..='Z') => Some(symbol as u8 - b'A'),
+            (_, 'a'..='z') => Some(symbol as u8 - b'a' + 26),
+            (_, '0'..='9') => Some(symbol as u8 - b'0' + 52),
+            (Alphabet::Standard, '+') | (Alphabet::UrlSafe, '-') => Some(62),
+            (Alphabet::Standard, '/') | (Alphabet::UrlSafe, '_') => Some(63),
+            _ => None,
+        }
+    }
+}
+
+/// How strictly the trailing `=` padding is checked when decoding
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Padding {
+    /// The input must be padded out to a multiple of 4 characters
+    #[default]
+    Strict,
+    /// Missing padding is accepted, too much padding is still an error
+    Lenient,
+}
+
+/// Errors that can occur when decoding base64
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Base64Error {
+    /// A character which is not in the alphabet, with its byte offset in the input
+    InvalidCharacter { character: char, index: usize },
+    /// The number of base64 characters, excluding padding, can not represent whole bytes
+    InvalidLength(usize),
+    /// The padding is misplaced or the wrong length, the value is the byte offset in the input
+    InvalidPadding(usize),
+}
+
+impl Display for Base64Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Base64Error::InvalidCharacter { character, index } => {
+                write!(f, "invalid base64 character {character:?} at index {index}")
+            }
+            Base64Error::InvalidLength(length) => {
+                write!(f, "invalid number of base64 characters ({length})")
+            }
+            Base64Error::InvalidPadding(index) => write!(f, "invalid padding at index {index}"),
+        }
+    }
+}
+
+impl Error for Base64Error {}
+
 /// A base 64 representation of some bytes
 #[derive(Debug, Default, Eq, PartialEq, PartialOrd)]
 pub struct Base64 {
     contents: String,
 }
 
-fn sextet_1(byte: u8) -> char {
-    let index = byte >> 2;
-    LOOKUP_TABLE[index as usize]
+fn sextet_1(byte: u8) -> u8 {
+    byte >> 2
 }
 
-fn sextet_2(byte_1: u8, byte_2: u8) -> char {
-    let mut index = (byte_1 << 4) & SEXTET_MASK;
-    index |= byte_2 >> 4;
-    LOOKUP_TABLE[index as usize]
+fn sextet_2(byte_1: u8, byte_2: u8) -> u8 {
+    let index = (byte_1 << 4) & SEXTET_MASK;
+    index | byte_2 >> 4
 }
 
-fn sextet_3(byte_1: u8, byte_2: u8) -> char {
-    let mut index = (byte_1 << 2) & SEXTET_MASK;
-    index |= byte_2 >> 6;
-    LOOKUP_TABLE[index as usize]
+fn sextet_3(byte_1: u8, byte_2: u8) -> u8 {
+    let index = (byte_1 << 2) & SEXTET_MASK;
+    index | byte_2 >> 6
 }
 
-fn sextet_4(byte: u8) -> char {
-    let index = byte & SEXTET_MASK;
-    LOOKUP_TABLE[index as usize]
+fn sextet_4(byte: u8) -> u8 {
+    byte & SEXTET_MASK
 }
 
-impl From<&[u8]> for Base64 {
-    fn from(bytes: &[u8]) -> Self {
-        let octets = bytes.chunks_exact(3);
+impl Base64 {
+    /// Encode `bytes` using the provided `alphabet`
+    pub fn encode(bytes: impl AsRef<[u8]>, alphabet: Alphabet) -> Self {
+        let octets = bytes.as_ref().chunks_exact(3);
         let mut contents = String::new();
         let remainder = octets.remainder();
+        let mut push = |index| contents.push(alphabet.symbol(index));
 
         for chunk in octets {
-            contents.push(sextet_1(chunk[0]));
-            contents.push(sextet_2(chunk[0], chunk[1]));
-            contents.push(sextet_3(chunk[1], chunk[2]));
-            contents.push(sextet_4(chunk[2]));
+            push(sextet_1(chunk[0]));
+            push(sextet_2(chunk[0], chunk[1]));
+            push(sextet_3(chunk[1], chunk[2]));
+            push(sextet_4(chunk[2]));
         }
 
         match remainder.len() {
             0 => {}
             1 => {
                 let byte = remainder[0];
-                contents.push(sextet_1(byte));
-                contents.push(sextet_2(byte, 0));
+                push(sextet_1(byte));
+                push(sextet_2(byte, 0));
                 contents.push(PAD);
                 contents.push(PAD);
             }
             2 => {
-                contents.push(sextet_1(remainder[0]));
-                contents.push(sextet_2(remainder[0], remainder[1]));
-                contents.push(sextet_3(remainder[1], 0));
+                push(sextet_1(remainder[0]));
+                push(sextet_2(remainder[0], remainder[1]));
+                push(sextet_3(remainder[1], 0));
                 contents.push(PAD);
             }
             _ => panic!("Should only every have 2 items in remainder"),
@@ -81,6 +153,30 @@ impl From<&[u8]> for Base64 {
 
         Self { contents }
     }
+
+    /// The encoded contents broken into lines of at most `width` characters, separated by CRLF. A
+    /// `width` of 0 doesn't wrap.
+    pub fn wrapped(&self, width: usize) -> String {
+        if width == 0 {
+            return self.contents.clone();
+        }
+        let lines = self.contents.as_bytes().chunks(width);
+        lines
+            .map(|line| std::str::from_utf8(line).expect("Base64 is always ASCII"))
+            .collect::<Vec<_>>()
+            .join("\r\n")
+    }
+
+    /// The encoded contents wrapped to the MIME line length
+    pub fn to_mime(&self) -> String {
+        self.wrapped(MIME_LINE_LENGTH)
+    }
+}
+
+impl From<&[u8]> for Base64 {
+    fn from(bytes: &[u8]) -> Self {
+        Self::encode(bytes, Alphabet::Standard)
+    }
 }
 
 impl From<&str> for Base64 {
@@ -89,10 +185,100 @@ impl From<&str> for Base64 {
     }
 }
 
+impl From<&Base64> for String {
+    fn from(base64: &Base64) -> Self {
+        base64.contents.clone()
+    }
+}
+
+impl AsRef<str> for Base64 {
+    fn as_ref(&self) -> &str {
+        &self.contents
+    }
+}
+
+/// Decodes base64 text back into bytes.
+///
+/// Newlines, `\n` and `\r`, are ignored anywhere in the input so that wrapped text can be
+/// decoded directly.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Base64Decoder {
+    alphabet: Alphabet,
+    padding: Padding,
+}
+
+impl Base64Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn alphabet(mut self, alphabet: Alphabet) -> Self {
+        self.alphabet = alphabet;
+        self
+    }
+
+    pub fn padding(mut self, padding: Padding) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn decode(&self, input: impl AsRef<str>) -> Result<Vec<u8>, Base64Error> {
+        let input = input.as_ref();
+        let mut sextets = vec![];
+        let mut pads = 0;
+        let mut pad_index = input.len();
+        for (index, character) in input.char_indices() {
+            match character {
+                '\n' | '\r' => {}
+                PAD => {
+                    pad_index = pad_index.min(index);
+                    pads += 1
+                }
+                _ if pads > 0 => return Err(Base64Error::InvalidPadding(index)),
+                _ => match self.alphabet.value(character) {
+                    Some(value) => sextets.push(value),
+                    None => return Err(Base64Error::InvalidCharacter { character, index }),
+                },
+            }
+        }
+
+        let remainder = sextets.len() % 4;
+        if remainder == 1 {
+            return Err(Base64Error::InvalidLength(sextets.len()));
+        }
+        let expected_pads = (4 - remainder) % 4;
+        let padding_ok = match self.padding {
+            Padding::Strict => pads == expected_pads,
+            Padding::Lenient => pads <= expected_pads,
+        };
+        if !padding_ok {
+            return Err(Base64Error::InvalidPadding(pad_index));
+        }
+
+        let mut bytes = Vec::with_capacity(sextets.len() * 3 / 4);
+        for chunk in sextets.chunks(4) {
+            bytes.push(chunk[0] << 2 | chunk[1] >> 4);
+            if let Some(third) = chunk.get(2) {
+                bytes.push(chunk[1] << 4 | third >> 2);
+            }
+            if let Some(fourth) = chunk.get(3) {
+                bytes.push(chunk[2] << 6 | fourth);
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+/// Decode standard alphabet, strictly padded, base64
+pub fn decode(input: impl AsRef<str>) -> Result<Vec<u8>, Base64Error> {
+    Base64Decoder::new().decode(input)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::hex::Hex;
+    use std::fs;
     use yare::parameterized;
 
     #[test]
@@ -144,7 +330,7 @@ mod test {
         let input = "49276d206b696c6c696e6720796f757220627261696e206c696b65206120706f69736f6e6f7573206d757368726f6f6d";
         let expected = "SSdtIGtpbGxpbmcgeW91ciBicmFpbiBsaWtlIGEgcG9pc29ub3VzIG11c2hyb29t";
 
-        let hex = Hex::from(input);
+        let hex = Hex::try_from(input).unwrap();
         let hex_bytes: &[u8] = (&hex).into();
         assert_eq!(
             Base64::from(hex_bytes),
@@ -153,4 +339,93 @@ mod test {
             }
         );
     }
+
+    #[test]
+    fn into_string() {
+        let base64 = Base64::from("light w");
+        assert_eq!(String::from(&base64), "bGlnaHQgdw==");
+        assert_eq!(base64.as_ref(), "bGlnaHQgdw==");
+    }
+
+    #[parameterized(
+    empty = { "", b"" },
+    d = { "ZA==", b"d" },
+    ma = { "TWE=", b"Ma" },
+    light_wor = { "bGlnaHQgd29y", b"light wor" },
+    light_work_ = { "bGlnaHQgd29yay4=", b"light work." },
+    newlines = { "bGln\naHQg\r\nd29y", b"light wor" },
+    )]
+    fn decode_standard(base_64: &str, expected: &[u8]) {
+        assert_eq!(decode(base_64), Ok(expected.to_vec()));
+    }
+
+    #[parameterized(
+    bad_character = { "ab*d", Base64Error::InvalidCharacter { character: '*', index: 2 } },
+    url_safe_character = { "ab-d", Base64Error::InvalidCharacter { character: '-', index: 2 } },
+    single_trailing = { "abcde===", Base64Error::InvalidLength(5) },
+    missing_padding = { "ZA", Base64Error::InvalidPadding(2) },
+    short_padding = { "ZA=", Base64Error::InvalidPadding(2) },
+    extra_padding = { "TWE==", Base64Error::InvalidPadding(3) },
+    data_after_padding = { "ZA==ZA==", Base64Error::InvalidPadding(4) },
+    )]
+    fn decode_strict_errors(base_64: &str, error: Base64Error) {
+        assert_eq!(decode(base_64), Err(error));
+    }
+
+    #[parameterized(
+    no_padding = { "ZA", b"d" },
+    partial_padding = { "ZA=", b"d" },
+    full_padding = { "ZA==", b"d" },
+    )]
+    fn decode_lenient(base_64: &str, expected: &[u8]) {
+        let decoder = Base64Decoder::new().padding(Padding::Lenient);
+        assert_eq!(decoder.decode(base_64), Ok(expected.to_vec()));
+    }
+
+    #[test]
+    fn decode_lenient_too_much_padding() {
+        let decoder = Base64Decoder::new().padding(Padding::Lenient);
+        assert_eq!(decoder.decode("TWE=="), Err(Base64Error::InvalidPadding(3)));
+    }
+
+    #[test]
+    fn url_safe_round_trip() {
+        let bytes = [0xfb, 0xff, 0xbf];
+        let base_64 = Base64::encode(bytes, Alphabet::UrlSafe);
+        assert_eq!(base_64.as_ref(), "-_-_");
+        assert_eq!(Base64::from(bytes.as_slice()).as_ref(), "+/+/");
+
+        let decoder = Base64Decoder::new().alphabet(Alphabet::UrlSafe);
+        assert_eq!(decoder.decode(base_64), Ok(bytes.to_vec()));
+        assert_eq!(
+            decoder.decode("+/+/"),
+            Err(Base64Error::InvalidCharacter {
+                character: '+',
+                index: 0
+            })
+        );
+    }
+
+    #[test]
+    fn wrapped_to_width() {
+        let base_64 = Base64::from("light work.");
+        assert_eq!(base_64.wrapped(6), "bGlnaH\r\nQgd29y\r\nay4=");
+        assert_eq!(base_64.wrapped(0), "bGlnaHQgd29yay4=");
+    }
+
+    #[test]
+    fn mime_round_trip() {
+        let bytes = (0..=255).collect::<Vec<u8>>();
+        let mime = Base64::from(bytes.as_slice()).to_mime();
+        assert!(mime.lines().all(|line| line.len() <= MIME_LINE_LENGTH));
+        assert_eq!(mime.lines().next().unwrap().len(), MIME_LINE_LENGTH);
+        assert_eq!(decode(mime), Ok(bytes));
+    }
+
+    #[test]
+    fn decode_wrapped_file() {
+        let contents = fs::read_to_string("tests/assets/6.txt").unwrap();
+        let bytes = decode(contents).unwrap();
+        assert_eq!(bytes.len(), 2876);
+    }
 }
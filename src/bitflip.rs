@@ -0,0 +1,382 @@
+//          Copyright Nick G 2023.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! CBC bitflipping attacks, https://cryptopals.com/sets/2/challenges/16
+//!
+//! CBC decryption XORs each decrypted block with the previous ciphertext block, or the IV for the
+//! first block. Flipping a bit in the previous block flips the same bit of the plaintext, at the
+//! cost of scrambling the plaintext of the block which was edited. Edits to the IV scramble
+//! nothing. Knowing part of the plaintext is enough to rewrite it to anything of the same length.
+
+use crate::block::CipherError;
+use crate::{cbc, pkcs};
+use rand::Rng;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+const BLOCK_SIZE: usize = 16;
+
+/// The plaintext placed before the quoted user data by [`CookieOracle`]
+pub const PREFIX: &[u8] = b"comment1=cooking%20MCs;userdata=";
+
+/// The plaintext placed after the quoted user data by [`CookieOracle`]
+pub const SUFFIX: &[u8] = b";comment2=%20like%20a%20pound%20of%20bacon";
+
+/// Errors from planning or applying bit flips
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BitflipError {
+    /// The known plaintext and the target must be the same length
+    LengthMismatch { known: usize, target: usize },
+    /// An edit falls outside of the IV or ciphertext
+    OutOfRange { index: usize, length: usize },
+    /// The edits to the next block would scramble this block, which is part of the plaintext being
+    /// changed
+    ScrambledEdit { block: usize },
+    /// The target is longer than can be injected
+    TargetTooLong { length: usize, max: usize },
+}
+
+impl Display for BitflipError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BitflipError::LengthMismatch { known, target } => write!(
+                f,
+                "known plaintext length ({known}) does not match target length ({target})"
+            ),
+            BitflipError::OutOfRange { index, length } => {
+                write!(f, "edit at index {index} is past the end, length {length}")
+            }
+            BitflipError::ScrambledEdit { block } => write!(
+                f,
+                "block {block} is part of the edit but is scrambled by editing the next block"
+            ),
+            BitflipError::TargetTooLong { length, max } => {
+                write!(f, "target length ({length}) is longer than {max}")
+            }
+        }
+    }
+}
+
+impl Error for BitflipError {}
+
+/// Where a byte is edited
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Location {
+    Iv,
+    Ciphertext,
+}
+
+/// XOR `mask` into the byte at `index` of the IV or ciphertext
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Edit {
+    pub location: Location,
+    pub index: usize,
+    pub mask: u8,
+}
+
+/// The edits which turn known plaintext into the target plaintext
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FlipPlan {
+    /// The bytes to change, one per plaintext byte which differs
+    pub edits: Vec<Edit>,
+    /// The plaintext blocks which will decrypt to garbage because their ciphertext was edited
+    pub scrambled_blocks: Vec<usize>,
+}
+
+impl FlipPlan {
+    /// Plan the edits which change `known`, the plaintext at `offset`, into `target`.
+    ///
+    /// Changing bytes past the first block of the edit would scramble the block before, so only
+    /// the first block touched may be partially covered by the edit and every change must be in
+    /// one block.
+    pub fn new(
+        offset: usize,
+        known: impl AsRef<[u8]>,
+        target: impl AsRef<[u8]>,
+        block_size: usize,
+    ) -> Result<Self, BitflipError> {
+        assert!(block_size > 0, "the block size must be at least one byte");
+        let known = known.as_ref();
+        let target = target.as_ref();
+        if known.len() != target.len() {
+            return Err(BitflipError::LengthMismatch {
+                known: known.len(),
+                target: target.len(),
+            });
+        }
+
+        let mut plan = FlipPlan::default();
+        for (position, (k, t)) in (offset..).zip(known.iter().zip(target)) {
+            let mask = k ^ t;
+            if mask == 0 {
+                continue;
+            }
+            let block = position / block_size;
+            let edit = match block {
+                0 => Edit {
+                    location: Location::Iv,
+                    index: position,
+                    mask,
+                },
+                _ => {
+                    if offset < block * block_size {
+                        return Err(BitflipError::ScrambledEdit { block: block - 1 });
+                    }
+                    if plan.scrambled_blocks.last() != Some(&(block - 1)) {
+                        plan.scrambled_blocks.push(block - 1);
+                    }
+                    Edit {
+                        location: Location::Ciphertext,
+                        index: position - block_size,
+                        mask,
+                    }
+                }
+            };
+            plan.edits.push(edit);
+        }
+        Ok(plan)
+    }
+
+    /// Apply the edits to `iv` and `ciphertext`. Nothing is changed if any edit is out of range.
+    pub fn apply(&self, iv: &mut [u8], ciphertext: &mut [u8]) -> Result<(), BitflipError> {
+        for edit in &self.edits {
+            let length = match edit.location {
+                Location::Iv => iv.len(),
+                Location::Ciphertext => ciphertext.len(),
+            };
+            if edit.index >= length {
+                return Err(BitflipError::OutOfRange {
+                    index: edit.index,
+                    length,
+                });
+            }
+        }
+        for edit in &self.edits {
+            match edit.location {
+                Location::Iv => iv[edit.index] ^= edit.mask,
+                Location::Ciphertext => ciphertext[edit.index] ^= edit.mask,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A cookie encrypted by [`CookieOracle`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cookie {
+    pub iv: [u8; BLOCK_SIZE],
+    pub ciphertext: Vec<u8>,
+}
+
+/// Encrypts user data into a cookie with AES-128-CBC and checks decrypted cookies for
+/// `;admin=true;`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CookieOracle {
+    key: [u8; BLOCK_SIZE],
+}
+
+impl Default for CookieOracle {
+    fn default() -> Self {
+        let mut rng = rand::thread_rng();
+        Self {
+            key: Rng::gen(&mut rng),
+        }
+    }
+}
+
+impl CookieOracle {
+    /// An oracle with a random key
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An oracle with a known key
+    pub fn with_key(key: [u8; BLOCK_SIZE]) -> Self {
+        Self { key }
+    }
+
+    /// Quote `userdata`, surround it with [`PREFIX`] and [`SUFFIX`] and encrypt it with a random
+    /// IV
+    pub fn encrypt(&self, userdata: impl AsRef<[u8]>) -> Cookie {
+        let iv = Rng::gen(&mut rand::thread_rng());
+        let plaintext = [PREFIX, &quote(userdata), SUFFIX].concat();
+        let padded = pkcs::pad(plaintext, BLOCK_SIZE as u8);
+        let ciphertext = cbc::encrypt(self.key, iv, padded).expect("the key is a valid length");
+        Cookie { iv, ciphertext }
+    }
+
+    /// Decrypt `cookie` and report whether it has an `admin=true` field
+    pub fn is_admin(&self, cookie: &Cookie) -> Result<bool, CipherError> {
+        let plaintext = cbc::cbc_decrypt_padded(self.key, cookie.iv, &cookie.ciphertext)?;
+        Ok(plaintext
+            .split(|b| *b == b';')
+            .any(|field| field == b"admin=true"))
+    }
+}
+
+/// Escape the `;` and `=` metacharacters, and `%` so the escaping can be undone
+pub fn quote(userdata: impl AsRef<[u8]>) -> Vec<u8> {
+    let mut quoted = Vec::with_capacity(userdata.as_ref().len());
+    for byte in userdata.as_ref() {
+        match byte {
+            b'%' => quoted.extend(b"%25"),
+            b';' => quoted.extend(b"%3B"),
+            b'=' => quoted.extend(b"%3D"),
+            _ => quoted.push(*byte),
+        }
+    }
+    quoted
+}
+
+/// Inject `target` into a cookie from `oracle` by editing the ciphertext.
+///
+/// The user data is filler, each block of `target` is preceded by a whole block of filler which
+/// gets scrambled and is flipped from the filler after it.
+pub fn inject(oracle: &CookieOracle, target: impl AsRef<[u8]>) -> Result<Cookie, BitflipError> {
+    let target = target.as_ref();
+    let alignment = (BLOCK_SIZE - PREFIX.len() % BLOCK_SIZE) % BLOCK_SIZE;
+    let filler =
+        vec![b'A'; alignment + target.len() + target.len().div_ceil(BLOCK_SIZE) * BLOCK_SIZE];
+    let mut cookie = oracle.encrypt(&filler);
+    for (i, chunk) in target.chunks(BLOCK_SIZE).enumerate() {
+        let offset = PREFIX.len() + alignment + (2 * i + 1) * BLOCK_SIZE;
+        let plan = FlipPlan::new(offset, &filler[..chunk.len()], chunk, BLOCK_SIZE)?;
+        plan.apply(&mut cookie.iv, &mut cookie.ciphertext)?;
+    }
+    Ok(cookie)
+}
+
+/// Inject `target` over the start of [`PREFIX`] by editing only the IV, nothing is scrambled.
+///
+/// The IV only reaches the first block, so `target` can be at most one block long.
+pub fn inject_with_iv(
+    oracle: &CookieOracle,
+    target: impl AsRef<[u8]>,
+) -> Result<Cookie, BitflipError> {
+    let target = target.as_ref();
+    if target.len() > BLOCK_SIZE {
+        return Err(BitflipError::TargetTooLong {
+            length: target.len(),
+            max: BLOCK_SIZE,
+        });
+    }
+    let known = &PREFIX[..target.len()];
+    let mut cookie = oracle.encrypt(b"");
+    FlipPlan::new(0, known, target, BLOCK_SIZE)?.apply(&mut cookie.iv, &mut cookie.ciphertext)?;
+    Ok(cookie)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use yare::parameterized;
+
+    const KEY: [u8; 16] = *b"YELLOW SUBMARINE";
+
+    #[test]
+    fn metacharacters_are_quoted() {
+        assert_eq!(quote(b";admin=true;"), b"%3Badmin%3Dtrue%3B");
+        assert_eq!(quote(b"100%"), b"100%25");
+        let oracle = CookieOracle::with_key(KEY);
+        assert!(!oracle.is_admin(&oracle.encrypt(";admin=true;")).unwrap());
+    }
+
+    #[test]
+    fn inject_admin() {
+        let oracle = CookieOracle::new();
+        let cookie = inject(&oracle, b";admin=true;").unwrap();
+        assert!(oracle.is_admin(&cookie).unwrap());
+    }
+
+    #[test]
+    fn inject_several_blocks() {
+        let oracle = CookieOracle::with_key(KEY);
+        let target = b";admin=true;role=superuser;a=b;c=d";
+        let cookie = inject(&oracle, target).unwrap();
+        assert!(oracle.is_admin(&cookie).unwrap());
+
+        let plaintext = cbc::cbc_decrypt_padded(KEY, cookie.iv, &cookie.ciphertext).unwrap();
+        for (i, chunk) in target.chunks(16).enumerate() {
+            let start = PREFIX.len() + (2 * i + 1) * 16;
+            assert_eq!(&plaintext[start..start + chunk.len()], chunk);
+        }
+    }
+
+    #[test]
+    fn inject_admin_with_iv() {
+        let oracle = CookieOracle::with_key(KEY);
+        let cookie = inject_with_iv(&oracle, b";admin=true;").unwrap();
+        assert!(oracle.is_admin(&cookie).unwrap());
+
+        let plaintext = cbc::cbc_decrypt_padded(KEY, cookie.iv, &cookie.ciphertext).unwrap();
+        assert_eq!(
+            plaintext,
+            [b";admin=true;".as_slice(), &PREFIX[12..], SUFFIX].concat()
+        );
+    }
+
+    #[test]
+    fn interior_edit_scrambles_previous_block() {
+        let plaintext = b"0123456789abcdef0123456789abcdef";
+        let mut iv = [0; 16];
+        let mut ciphertext = cbc::encrypt(KEY, iv, plaintext).unwrap();
+        let plan = FlipPlan::new(20, b"4567", b"WXYZ", 16).unwrap();
+        assert_eq!(plan.scrambled_blocks, vec![0]);
+        assert_eq!(plan.edits.len(), 4);
+        plan.apply(&mut iv, &mut ciphertext).unwrap();
+
+        let decrypted = cbc::cbc_decrypt(KEY, iv, ciphertext).unwrap();
+        assert_ne!(&decrypted[..16], &plaintext[..16]);
+        assert_eq!(&decrypted[16..], b"0123WXYZ89abcdef");
+    }
+
+    #[parameterized(
+    iv_only = { 0, b"abc".as_slice(), b"xyz".as_slice(), vec![] },
+    spans_blocks = { 14, b"abcd".as_slice(), b"xbcd".as_slice(), vec![] },
+    second_block = { 32, b"abcd".as_slice(), b"xbcz".as_slice(), vec![1] },
+    unchanged = { 40, b"same".as_slice(), b"same".as_slice(), vec![] },
+    )]
+    fn scrambled_blocks(offset: usize, known: &[u8], target: &[u8], scrambled: Vec<usize>) {
+        let plan = FlipPlan::new(offset, known, target, 16).unwrap();
+        assert_eq!(plan.scrambled_blocks, scrambled);
+    }
+
+    #[test]
+    fn plan_errors() {
+        assert_eq!(
+            FlipPlan::new(0, b"abc", b"abcd", 16),
+            Err(BitflipError::LengthMismatch {
+                known: 3,
+                target: 4
+            })
+        );
+        assert_eq!(
+            FlipPlan::new(14, b"abcd", b"xyzd", 16),
+            Err(BitflipError::ScrambledEdit { block: 0 })
+        );
+        assert_eq!(
+            FlipPlan::new(30, b"abcd", b"xbcz", 16),
+            Err(BitflipError::ScrambledEdit { block: 1 })
+        );
+        assert_eq!(
+            inject_with_iv(&CookieOracle::with_key(KEY), [b'A'; 17]),
+            Err(BitflipError::TargetTooLong {
+                length: 17,
+                max: 16
+            })
+        );
+        let plan = FlipPlan::new(40, b"a", b"b", 16).unwrap();
+        let mut iv = [0; 16];
+        let mut ciphertext = [0; 16];
+        assert_eq!(
+            plan.apply(&mut iv, &mut ciphertext),
+            Err(BitflipError::OutOfRange {
+                index: 24,
+                length: 16
+            })
+        );
+        assert_eq!(ciphertext, [0; 16]);
+    }
+}
@@ -0,0 +1,236 @@
+//          Copyright Nick G 2023.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! Block ciphers which can be used with the [`ecb`](crate::ecb) and [`cbc`](crate::cbc) modes.
+//!
+//! The modes work with any cipher implementing [`BlockEncrypt`] and [`BlockDecrypt`], this module
+//! provides [`Aes`], which picks the AES variant from the key length at runtime, and [`Toy`], an
+//! insecure 8 byte block cipher for exercising the modes with a non AES block size.
+
+use crate::pkcs::PaddingError;
+use aes::cipher::consts::{U16, U8};
+use aes::cipher::inout::InOutBuf;
+use aes::cipher::{
+    impl_simple_block_encdec, Block, BlockClosure, BlockDecrypt, BlockEncrypt, BlockSizeUser,
+    InvalidLength, Key, KeyInit, KeySizeUser,
+};
+use aes::{Aes128, Aes192, Aes256};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// Errors from the block cipher modes when given malformed keys, IVs or input
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CipherError {
+    /// The key length, in bytes, is not supported by the cipher
+    InvalidKeyLength(usize),
+    /// The IV must be exactly one block long
+    InvalidIvLength { expected: usize, actual: usize },
+    /// The nonce does not match the length required by the counter layout
+    InvalidNonceLength { expected: usize, actual: usize },
+    /// The initial counter does not fit in the counter layout
+    CounterOutOfRange { counter: u64, max: u64 },
+    /// The input length is not a multiple of the block size
+    NotBlockAligned { length: usize, block_size: usize },
+    /// The decrypted plaintext did not end in valid PKCS#7 padding
+    Padding(PaddingError),
+}
+
+impl Display for CipherError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CipherError::InvalidKeyLength(length) => write!(f, "invalid key length ({length})"),
+            CipherError::InvalidIvLength { expected, actual } => {
+                write!(f, "invalid IV length ({actual}), expected {expected}")
+            }
+            CipherError::InvalidNonceLength { expected, actual } => {
+                write!(f, "invalid nonce length ({actual}), expected {expected}")
+            }
+            CipherError::CounterOutOfRange { counter, max } => {
+                write!(f, "counter ({counter}) is larger than the maximum ({max})")
+            }
+            CipherError::NotBlockAligned { length, block_size } => write!(
+                f,
+                "input length ({length}) is not a multiple of the block size ({block_size})"
+            ),
+            CipherError::Padding(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Error for CipherError {}
+
+impl From<PaddingError> for CipherError {
+    fn from(error: PaddingError) -> Self {
+        CipherError::Padding(error)
+    }
+}
+
+/// Ensure `input` is made up of whole blocks for the cipher `C`
+pub(crate) fn check_aligned<C: BlockSizeUser>(input: &[u8]) -> Result<(), CipherError> {
+    match input.len() % C::block_size() {
+        0 => Ok(()),
+        _ => Err(CipherError::NotBlockAligned {
+            length: input.len(),
+            block_size: C::block_size(),
+        }),
+    }
+}
+
+/// View `data`, which must be block aligned, as blocks to be processed in place. Passing the blocks
+/// to the cipher all at once lets it process several in parallel.
+pub(crate) fn blocks_in_place<C: BlockSizeUser>(data: &mut [u8]) -> InOutBuf<'_, '_, Block<C>> {
+    let (blocks, tail) = InOutBuf::from(data).into_chunks();
+    debug_assert!(tail.is_empty(), "data must be block aligned");
+    blocks
+}
+
+/// View `input` and `output`, which must be the same block aligned length, as blocks to be
+/// processed from `input` into `output`
+pub(crate) fn blocks_b2b<'a, C: BlockSizeUser>(
+    input: &'a [u8],
+    output: &'a mut [u8],
+) -> InOutBuf<'a, 'a, Block<C>> {
+    let buffer = InOutBuf::new(input, output).expect("input and output are the same length");
+    let (blocks, tail) = buffer.into_chunks();
+    debug_assert!(tail.is_empty(), "input must be block aligned");
+    blocks
+}
+
+/// Ensure `iv` is exactly one block for the cipher `C`
+pub(crate) fn check_iv<C: BlockSizeUser>(iv: &[u8]) -> Result<(), CipherError> {
+    match iv.len() == C::block_size() {
+        true => Ok(()),
+        false => Err(CipherError::InvalidIvLength {
+            expected: C::block_size(),
+            actual: iv.len(),
+        }),
+    }
+}
+
+/// AES with the key size selected by the length of the key
+#[derive(Clone)]
+pub enum Aes {
+    Aes128(Aes128),
+    Aes192(Aes192),
+    Aes256(Aes256),
+}
+
+impl Aes {
+    /// Create the AES variant matching `key`, which must be 16, 24 or 32 bytes long
+    pub fn new_from_slice(key: &[u8]) -> Result<Self, InvalidLength> {
+        match key.len() {
+            16 => Ok(Aes::Aes128(Aes128::new_from_slice(key)?)),
+            24 => Ok(Aes::Aes192(Aes192::new_from_slice(key)?)),
+            32 => Ok(Aes::Aes256(Aes256::new_from_slice(key)?)),
+            _ => Err(InvalidLength),
+        }
+    }
+
+    /// Like [`Aes::new_from_slice`] but reporting the key length on failure
+    pub fn new_checked(key: &[u8]) -> Result<Self, CipherError> {
+        Self::new_from_slice(key).map_err(|_| CipherError::InvalidKeyLength(key.len()))
+    }
+}
+
+impl BlockSizeUser for Aes {
+    type BlockSize = U16;
+}
+
+// Forwarding to the backend of the wrapped cipher, rather than a block at a time, keeps the
+// hardware parallel block processing for `encrypt_blocks` and `decrypt_blocks`
+impl BlockEncrypt for Aes {
+    fn encrypt_with_backend(&self, f: impl BlockClosure<BlockSize = U16>) {
+        match self {
+            Aes::Aes128(aes) => aes.encrypt_with_backend(f),
+            Aes::Aes192(aes) => aes.encrypt_with_backend(f),
+            Aes::Aes256(aes) => aes.encrypt_with_backend(f),
+        }
+    }
+}
+
+impl BlockDecrypt for Aes {
+    fn decrypt_with_backend(&self, f: impl BlockClosure<BlockSize = U16>) {
+        match self {
+            Aes::Aes128(aes) => aes.decrypt_with_backend(f),
+            Aes::Aes192(aes) => aes.decrypt_with_backend(f),
+            Aes::Aes256(aes) => aes.decrypt_with_backend(f),
+        }
+    }
+}
+
+/// A trivially breakable cipher with an 8 byte block and an 8 byte key.
+///
+/// Each block is XORed with the key and then rotated left by one byte. Only meant for testing the
+/// modes with something other than a 16 byte block.
+#[derive(Clone)]
+pub struct Toy {
+    key: Key<Toy>,
+}
+
+impl KeySizeUser for Toy {
+    type KeySize = U8;
+}
+
+impl KeyInit for Toy {
+    fn new(key: &Key<Self>) -> Self {
+        Self { key: *key }
+    }
+}
+
+impl_simple_block_encdec!(
+    Toy, U8, cipher, block,
+    encrypt: {
+        let mut bytes = *block.get_in();
+        bytes.iter_mut().zip(cipher.key.iter()).for_each(|(b, k)| *b ^= k);
+        bytes.rotate_left(1);
+        *block.get_out() = bytes;
+    }
+    decrypt: {
+        let mut bytes = *block.get_in();
+        bytes.rotate_right(1);
+        bytes.iter_mut().zip(cipher.key.iter()).for_each(|(b, k)| *b ^= k);
+        *block.get_out() = bytes;
+    }
+);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use yare::parameterized;
+
+    #[parameterized(
+    empty = { 0 },
+    short = { 15 },
+    between = { 20 },
+    long = { 33 },
+    )]
+    fn aes_invalid_key_length(length: usize) {
+        assert!(Aes::new_from_slice(&vec![0; length]).is_err());
+    }
+
+    #[test]
+    fn aes_new_checked_reports_length() {
+        assert!(matches!(
+            Aes::new_checked(&[0; 17]),
+            Err(CipherError::InvalidKeyLength(17))
+        ));
+    }
+
+    #[test]
+    fn aes_block_size() {
+        assert_eq!(Aes::block_size(), 16);
+    }
+
+    #[test]
+    fn toy_round_trip() {
+        let toy = Toy::new_from_slice(b"toy key!").unwrap();
+        let plain = Block::<Toy>::clone_from_slice(b"8 bytes!");
+        let mut block = plain;
+        toy.encrypt_block(&mut block);
+        assert_ne!(block, plain);
+        toy.decrypt_block(&mut block);
+        assert_eq!(block, plain);
+    }
+}
@@ -0,0 +1,284 @@
+//          Copyright Nick G 2023.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! Byte-at-a-time ECB decryption, https://cryptopals.com/sets/2/challenges/12 and
+//! https://cryptopals.com/sets/2/challenges/14
+//!
+//! The oracle encrypts `prefix || attacker input || secret` under ECB with a fixed key. The
+//! prefix may be empty, or any number of bytes, but must be the same on every call. By controlling
+//! how much input precedes the secret, one unknown byte at a time is pushed to the end of a block
+//! and matched against a dictionary of every possible final byte.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// The largest block size that will be searched for
+const MAX_BLOCK_SIZE: usize = 64;
+
+/// Filler bytes, two are needed to tell the prefix or secret apart from the attacker input
+const FILLERS: [u8; 2] = [b'A', b'B'];
+
+/// Errors from running the byte-at-a-time attack
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ByteAtATimeError {
+    /// The ciphertext length never changed with the input length
+    BlockSizeNotFound,
+    /// Repeated plaintext blocks did not produce repeated ciphertext blocks
+    NotEcb,
+    /// The position of the attacker input could not be aligned to a block
+    PrefixNotFound,
+    /// None of the possible byte values matched the secret byte at this index
+    ByteNotFound(usize),
+}
+
+impl Display for ByteAtATimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ByteAtATimeError::BlockSizeNotFound => write!(f, "unable to determine block size"),
+            ByteAtATimeError::NotEcb => write!(f, "the oracle is not using ECB"),
+            ByteAtATimeError::PrefixNotFound => write!(f, "unable to determine prefix length"),
+            ByteAtATimeError::ByteNotFound(index) => {
+                write!(f, "unable to recover the secret byte at index {index}")
+            }
+        }
+    }
+}
+
+impl Error for ByteAtATimeError {}
+
+/// The result of a successful byte-at-a-time attack
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Recovered {
+    /// The secret appended by the oracle
+    pub secret: Vec<u8>,
+    /// The detected cipher block size
+    pub block_size: usize,
+    /// The detected length of the bytes the oracle places before the attacker input
+    pub prefix_length: usize,
+    /// The number of times the oracle was queried
+    pub queries: usize,
+}
+
+/// Recover the secret suffix from an ECB encryption `oracle`
+pub fn decrypt<O>(oracle: O) -> Result<Recovered, ByteAtATimeError>
+where
+    O: Fn(&[u8]) -> Vec<u8>,
+{
+    let mut queries = 0;
+    let mut counted = |input: &[u8]| {
+        queries += 1;
+        oracle(input)
+    };
+
+    let (block_size, unknown_length) = find_block_size(&mut counted)?;
+    if !is_ecb(&mut counted, block_size) {
+        return Err(ByteAtATimeError::NotEcb);
+    }
+    let prefix_length = find_prefix_length(&mut counted, block_size)?;
+    let secret_length = unknown_length
+        .checked_sub(prefix_length)
+        .ok_or(ByteAtATimeError::PrefixNotFound)?;
+
+    let secret = recover_secret(&mut counted, block_size, prefix_length, secret_length)?;
+    Ok(Recovered {
+        secret,
+        block_size,
+        prefix_length,
+        queries,
+    })
+}
+
+/// Returns the block size and the combined length of the prefix and secret.
+///
+/// Adding input one byte at a time eventually adds a whole block of padding, the size of the jump
+/// is the block size. Right before the jump the input exactly filled the final block.
+fn find_block_size(
+    oracle: &mut impl FnMut(&[u8]) -> Vec<u8>,
+) -> Result<(usize, usize), ByteAtATimeError> {
+    let initial = oracle(&[]).len();
+    for added in 1..=MAX_BLOCK_SIZE {
+        let length = oracle(&vec![FILLERS[0]; added]).len();
+        if let Some(block_size) = length.checked_sub(initial).filter(|size| *size > 0) {
+            // Less unknown output than input means nothing is padded to a block, as from a
+            // stream cipher with no prefix or secret
+            let unknown_length = initial
+                .checked_sub(added)
+                .ok_or(ByteAtATimeError::BlockSizeNotFound)?;
+            return Ok((block_size, unknown_length));
+        }
+    }
+    Err(ByteAtATimeError::BlockSizeNotFound)
+}
+
+fn is_ecb(oracle: &mut impl FnMut(&[u8]) -> Vec<u8>, block_size: usize) -> bool {
+    // Three blocks of input guarantees two whole identical blocks no matter the prefix length
+    let encrypted = oracle(&vec![FILLERS[0]; block_size * 3]);
+    let blocks = encrypted.chunks_exact(block_size).collect::<Vec<_>>();
+    blocks.windows(2).any(|pair| pair[0] == pair[1])
+}
+
+/// The smallest amount of filler which makes two identical adjacent blocks reveals where the
+/// attacker input starts. Any repeats already in the encrypted prefix are ignored and the filler
+/// byte is varied in case the prefix ends, or the secret starts, with the filler.
+fn find_prefix_length(
+    oracle: &mut impl FnMut(&[u8]) -> Vec<u8>,
+    block_size: usize,
+) -> Result<usize, ByteAtATimeError> {
+    let baseline = oracle(&[]);
+    let existing = baseline.chunks_exact(block_size).collect::<HashSet<_>>();
+
+    let mut candidates = vec![];
+    for filler in FILLERS {
+        for extra in 0..block_size {
+            let encrypted = oracle(&vec![filler; block_size * 2 + extra]);
+            let blocks = encrypted.chunks_exact(block_size).collect::<Vec<_>>();
+            let repeat = blocks
+                .windows(2)
+                .position(|pair| pair[0] == pair[1] && !existing.contains(pair[0]));
+            if let Some(index) = repeat {
+                candidates.extend((index * block_size).checked_sub(extra));
+                break;
+            }
+        }
+    }
+    candidates
+        .into_iter()
+        .min()
+        .ok_or(ByteAtATimeError::PrefixNotFound)
+}
+
+fn recover_secret(
+    oracle: &mut impl FnMut(&[u8]) -> Vec<u8>,
+    block_size: usize,
+    prefix_length: usize,
+    secret_length: usize,
+) -> Result<Vec<u8>, ByteAtATimeError> {
+    // Fill out the final prefix block so that attacker input starts on a block boundary
+    let alignment = (block_size - prefix_length % block_size) % block_size;
+    let first_block = (prefix_length + alignment) / block_size;
+    let block_range = |index: usize| index * block_size..(index + 1) * block_size;
+
+    let mut secret = Vec::with_capacity(secret_length);
+    for index in 0..secret_length {
+        let shift = block_size - 1 - index % block_size;
+        let input = vec![FILLERS[0]; alignment + shift];
+        let target_block = block_range(first_block + index / block_size);
+        let target = oracle(&input)[target_block].to_vec();
+
+        // The block being matched is the previous `block_size - 1` known bytes and a guess
+        let mut known = vec![FILLERS[0]; block_size - 1];
+        known.extend(&secret);
+        let mut guess_input = vec![FILLERS[0]; alignment];
+        guess_input.extend(&known[known.len() - (block_size - 1)..]);
+        guess_input.push(0);
+
+        let byte = (0..=u8::MAX).find(|guess| {
+            *guess_input.last_mut().unwrap() = *guess;
+            oracle(&guess_input)[block_range(first_block)] == target[..]
+        });
+        match byte {
+            Some(byte) => secret.push(byte),
+            None => return Err(ByteAtATimeError::ByteNotFound(index)),
+        }
+    }
+    Ok(secret)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::base64;
+    use crate::block::Toy;
+    use crate::{cbc, ctr, ecb};
+    use aes::cipher::KeyInit;
+    use yare::parameterized;
+
+    const KEY: &[u8; 16] = b"YELLOW SUBMARINE";
+    const SECRET: &str = "Um9sbGluJyBpbiBteSA1LjAKV2l0aCBteSByYWctdG9wIGRvd24gc28gbXkgaGFpciBjYW4gYmxvdwpUaGUgZ2lybGllcyBvbiBzdGFuZGJ5IHdhdmluZyBqdXN0IHRvIHNheSBoaQpEaWQgeW91IHN0b3A/IE5vLCBJIGp1c3QgZHJvdmUgYnkK";
+
+    fn ecb_oracle(prefix: Vec<u8>, secret: Vec<u8>) -> impl Fn(&[u8]) -> Vec<u8> {
+        move |input| ecb::encrypt_padded(KEY, [&prefix, input, &secret].concat()).unwrap()
+    }
+
+    #[test]
+    fn challenge_12() {
+        let secret = base64::decode(SECRET).unwrap();
+        let recovered = decrypt(ecb_oracle(vec![], secret.clone())).unwrap();
+        assert_eq!(recovered.secret, secret);
+        assert_eq!(recovered.block_size, 16);
+        assert_eq!(recovered.prefix_length, 0);
+    }
+
+    #[parameterized(
+    one = { 1 },
+    one_short_of_block = { 15 },
+    block = { 16 },
+    over_block = { 17 },
+    several_blocks = { 37 },
+    )]
+    fn challenge_14(prefix_length: usize) {
+        let secret = base64::decode(SECRET).unwrap();
+        let prefix = (0..prefix_length as u8)
+            .map(|b| b.wrapping_mul(97))
+            .collect();
+        let recovered = decrypt(ecb_oracle(prefix, secret.clone())).unwrap();
+        assert_eq!(recovered.secret, secret);
+        assert_eq!(recovered.prefix_length, prefix_length);
+    }
+
+    #[test]
+    fn prefix_ends_and_secret_starts_with_filler() {
+        let prefix = b"12345AAA".to_vec();
+        let secret = b"AAAsecret".to_vec();
+        let recovered = decrypt(ecb_oracle(prefix, secret.clone())).unwrap();
+        assert_eq!(recovered.secret, secret);
+        assert_eq!(recovered.prefix_length, 8);
+    }
+
+    #[test]
+    fn prefix_with_repeated_blocks() {
+        let prefix = [[7u8; 16], [7u8; 16]].concat();
+        let secret = b"hidden".to_vec();
+        let recovered = decrypt(ecb_oracle(prefix, secret.clone())).unwrap();
+        assert_eq!(recovered.secret, secret);
+        assert_eq!(recovered.prefix_length, 32);
+    }
+
+    #[test]
+    fn toy_cipher_block_size() {
+        let toy = Toy::new_from_slice(b"toy key!").unwrap();
+        let oracle = |input: &[u8]| {
+            ecb::encrypt_padded_with(&toy, [b"pre", input, b"the secret"].concat()).unwrap()
+        };
+        let recovered = decrypt(oracle).unwrap();
+        assert_eq!(recovered.secret, b"the secret");
+        assert_eq!(recovered.block_size, 8);
+        assert_eq!(recovered.prefix_length, 3);
+    }
+
+    #[test]
+    fn cbc_is_not_ecb() {
+        let oracle =
+            |input: &[u8]| cbc::encrypt_padded(KEY, [0; 16], [input, b"secret"].concat()).unwrap();
+        assert_eq!(decrypt(oracle), Err(ByteAtATimeError::NotEcb));
+    }
+
+    #[test]
+    fn stream_oracle() {
+        let oracle = |input: &[u8]| ctr::encrypt(KEY, [0; 8], input).unwrap();
+        assert_eq!(decrypt(oracle), Err(ByteAtATimeError::BlockSizeNotFound));
+        assert_eq!(
+            decrypt(|input: &[u8]| input.to_vec()),
+            Err(ByteAtATimeError::BlockSizeNotFound)
+        );
+    }
+
+    #[test]
+    fn reports_queries() {
+        let recovered = decrypt(ecb_oracle(vec![], b"abc".to_vec())).unwrap();
+        assert!(recovered.queries > 3);
+    }
+}
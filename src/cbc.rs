@@ -3,104 +3,455 @@
 //    (See accompanying file LICENSE or copy at
 //          https://www.boost.org/LICENSE_1_0.txt)
 
-use aes::cipher::{BlockDecrypt, BlockEncrypt, BlockSizeUser, KeyInit};
-use aes::{Aes128, Block};
+use crate::block::{blocks_b2b, blocks_in_place, check_aligned, check_iv, Aes, CipherError};
+use crate::pkcs;
+use aes::cipher::generic_array::{ArrayLength, GenericArray};
+use aes::cipher::{Block, BlockDecrypt, BlockEncrypt};
+
+/// The number of blocks decrypted together by [`cbc_decrypt_in_place_with`]
+const PARALLEL_BLOCKS: usize = 32;
 
 trait Xor {
     fn xor(&mut self, other: &Self);
 }
 
-impl Xor for Block {
+impl<N: ArrayLength<u8>> Xor for GenericArray<u8, N> {
     fn xor(&mut self, other: &Self) {
         self.as_mut_slice()
             .iter_mut()
             .zip(other.as_slice().iter())
-            .for_each(|(a, b)| *a = *a ^ *b);
+            .for_each(|(a, b)| *a ^= *b);
     }
 }
 
-pub fn encrypt(key: impl AsRef<[u8]>, iv: impl AsRef<[u8]>, raw: impl AsRef<[u8]>) -> Vec<u8> {
+/// Encrypt `raw` with AES, the AES variant is chosen by the length of `key`
+pub fn encrypt(
+    key: impl AsRef<[u8]>,
+    iv: impl AsRef<[u8]>,
+    raw: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CipherError> {
+    let encryptor = Aes::new_checked(key.as_ref())?;
+    encrypt_with(&encryptor, iv, raw)
+}
+
+/// Encrypt `raw` with any block cipher
+pub fn encrypt_with<C: BlockEncrypt>(
+    encryptor: &C,
+    iv: impl AsRef<[u8]>,
+    raw: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CipherError> {
     let raw = raw.as_ref();
-    let encryptor = Aes128::new_from_slice(key.as_ref()).unwrap();
+    let iv = iv.as_ref();
+    check_iv::<C>(iv)?;
+    check_aligned::<C>(raw)?;
     let mut output = vec![0; raw.len()];
     let in_blocks = raw
-        .chunks_exact(Aes128::block_size())
-        .map(Block::from_slice);
+        .chunks_exact(C::block_size())
+        .map(Block::<C>::from_slice);
     let out_blocks = output
-        .chunks_exact_mut(Aes128::block_size())
-        .map(Block::from_mut_slice);
+        .chunks_exact_mut(C::block_size())
+        .map(Block::<C>::from_mut_slice);
 
-    let mut iv_block = Block::clone_from_slice(iv.as_ref());
+    let mut iv_block = Block::<C>::clone_from_slice(iv);
     for (in_block, out_block) in in_blocks.zip(out_blocks) {
         iv_block.xor(in_block);
         encryptor.encrypt_block_b2b(&iv_block, out_block);
-        iv_block = *out_block;
+        iv_block = out_block.clone();
     }
-    output
+    Ok(output)
 }
 
-fn cbc_decrypt(
+/// Decrypt `encrypted` with AES, the AES variant is chosen by the length of `key`
+pub fn cbc_decrypt(
     key: impl AsRef<[u8]>,
     iv: impl AsRef<[u8]>,
     encrypted: impl AsRef<[u8]>,
-) -> Vec<u8> {
+) -> Result<Vec<u8>, CipherError> {
+    let decryptor = Aes::new_checked(key.as_ref())?;
+    cbc_decrypt_with(&decryptor, iv, encrypted)
+}
+
+/// Decrypt `encrypted` with any block cipher
+pub fn cbc_decrypt_with<C: BlockDecrypt>(
+    decryptor: &C,
+    iv: impl AsRef<[u8]>,
+    encrypted: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CipherError> {
     let encrypted = encrypted.as_ref();
-    let decryptor = Aes128::new_from_slice(key.as_ref()).unwrap();
+    let iv = iv.as_ref();
+    check_iv::<C>(iv)?;
+    check_aligned::<C>(encrypted)?;
     let mut output = vec![0; encrypted.len()];
-    let in_blocks = encrypted
-        .chunks_exact(Aes128::block_size())
-        .map(Block::from_slice);
-    let out_blocks = output
-        .chunks_exact_mut(Aes128::block_size())
-        .map(Block::from_mut_slice);
+    // Unlike encryption every block can be decrypted at once, the chaining is only an XOR after
+    decryptor.decrypt_blocks_inout(blocks_b2b::<C>(encrypted, &mut output));
+    let previous_blocks = iv
+        .chunks_exact(C::block_size())
+        .chain(encrypted.chunks_exact(C::block_size()));
+    for (out_block, previous) in output
+        .chunks_exact_mut(C::block_size())
+        .zip(previous_blocks)
+    {
+        Block::<C>::from_mut_slice(out_block).xor(Block::<C>::from_slice(previous));
+    }
+    Ok(output)
+}
 
-    let mut iv_block = Block::clone_from_slice(iv.as_ref());
-    for (in_block, out_block) in in_blocks.zip(out_blocks) {
-        decryptor.decrypt_block_b2b(in_block, out_block);
-        out_block.xor(&iv_block);
-        iv_block = *in_block;
+/// Encrypt `data` in place with AES, the AES variant is chosen by the length of `key`
+pub fn encrypt_in_place(
+    key: impl AsRef<[u8]>,
+    iv: impl AsRef<[u8]>,
+    data: &mut [u8],
+) -> Result<(), CipherError> {
+    let encryptor = Aes::new_checked(key.as_ref())?;
+    encrypt_in_place_with(&encryptor, iv, data)
+}
+
+/// Encrypt `data` in place with any block cipher
+pub fn encrypt_in_place_with<C: BlockEncrypt>(
+    encryptor: &C,
+    iv: impl AsRef<[u8]>,
+    data: &mut [u8],
+) -> Result<(), CipherError> {
+    let iv = iv.as_ref();
+    check_iv::<C>(iv)?;
+    check_aligned::<C>(data)?;
+    let mut previous = Block::<C>::clone_from_slice(iv);
+    for block in data.chunks_exact_mut(C::block_size()) {
+        let block = Block::<C>::from_mut_slice(block);
+        block.xor(&previous);
+        encryptor.encrypt_block(block);
+        previous.clone_from(block);
+    }
+    Ok(())
+}
+
+/// Decrypt `data` in place with AES, the AES variant is chosen by the length of `key`
+pub fn cbc_decrypt_in_place(
+    key: impl AsRef<[u8]>,
+    iv: impl AsRef<[u8]>,
+    data: &mut [u8],
+) -> Result<(), CipherError> {
+    let decryptor = Aes::new_checked(key.as_ref())?;
+    cbc_decrypt_in_place_with(&decryptor, iv, data)
+}
+
+/// Decrypt `data` in place with any block cipher
+pub fn cbc_decrypt_in_place_with<C: BlockDecrypt>(
+    decryptor: &C,
+    iv: impl AsRef<[u8]>,
+    data: &mut [u8],
+) -> Result<(), CipherError> {
+    let iv = iv.as_ref();
+    check_iv::<C>(iv)?;
+    check_aligned::<C>(data)?;
+    // Decrypting in place overwrites the ciphertext needed for the chaining, so a group of blocks
+    // is saved before being decrypted together
+    let mut previous = Block::<C>::clone_from_slice(iv);
+    let mut saved: [Block<C>; PARALLEL_BLOCKS] = Default::default();
+    for chunk in data.chunks_mut(C::block_size() * PARALLEL_BLOCKS) {
+        let count = chunk.len() / C::block_size();
+        for (saved, block) in saved.iter_mut().zip(chunk.chunks_exact(C::block_size())) {
+            saved.copy_from_slice(block);
+        }
+        decryptor.decrypt_blocks_inout(blocks_in_place::<C>(chunk));
+        for (index, block) in chunk.chunks_exact_mut(C::block_size()).enumerate() {
+            let chained = match index {
+                0 => &previous,
+                _ => &saved[index - 1],
+            };
+            Block::<C>::from_mut_slice(block).xor(chained);
+        }
+        previous.clone_from(&saved[count - 1]);
     }
-    output
+    Ok(())
+}
+
+/// Pad `raw` with PKCS#7 and encrypt it with AES, the AES variant is chosen by the length of `key`
+pub fn encrypt_padded(
+    key: impl AsRef<[u8]>,
+    iv: impl AsRef<[u8]>,
+    raw: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CipherError> {
+    let encryptor = Aes::new_checked(key.as_ref())?;
+    encrypt_padded_with(&encryptor, iv, raw)
+}
+
+/// Pad `raw` with PKCS#7 and encrypt it with any block cipher
+pub fn encrypt_padded_with<C: BlockEncrypt>(
+    encryptor: &C,
+    iv: impl AsRef<[u8]>,
+    raw: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CipherError> {
+    encrypt_with(encryptor, iv, pkcs::pad(raw, C::block_size() as u8))
+}
+
+/// Decrypt `encrypted` with AES and remove the PKCS#7 padding, the AES variant is chosen by the
+/// length of `key`
+pub fn cbc_decrypt_padded(
+    key: impl AsRef<[u8]>,
+    iv: impl AsRef<[u8]>,
+    encrypted: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CipherError> {
+    let decryptor = Aes::new_checked(key.as_ref())?;
+    cbc_decrypt_padded_with(&decryptor, iv, encrypted)
+}
+
+/// Decrypt `encrypted` with any block cipher and remove the PKCS#7 padding
+pub fn cbc_decrypt_padded_with<C: BlockDecrypt>(
+    decryptor: &C,
+    iv: impl AsRef<[u8]>,
+    encrypted: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CipherError> {
+    let decrypted = cbc_decrypt_with(decryptor, iv, encrypted)?;
+    Ok(pkcs::unpad(decrypted, C::block_size() as u8)?)
+}
+
+/// Pad `data` with PKCS#7 and encrypt it in place with AES, the AES variant is chosen by the
+/// length of `key`. No allocation happens if `data` has a block of spare capacity.
+pub fn encrypt_padded_in_place(
+    key: impl AsRef<[u8]>,
+    iv: impl AsRef<[u8]>,
+    data: &mut Vec<u8>,
+) -> Result<(), CipherError> {
+    let encryptor = Aes::new_checked(key.as_ref())?;
+    encrypt_padded_in_place_with(&encryptor, iv, data)
+}
+
+/// Pad `data` with PKCS#7 and encrypt it in place with any block cipher
+pub fn encrypt_padded_in_place_with<C: BlockEncrypt>(
+    encryptor: &C,
+    iv: impl AsRef<[u8]>,
+    data: &mut Vec<u8>,
+) -> Result<(), CipherError> {
+    let iv = iv.as_ref();
+    check_iv::<C>(iv)?;
+    pkcs::pad_in_place(data, C::block_size() as u8);
+    encrypt_in_place_with(encryptor, iv, data)
+}
+
+/// Decrypt `data` in place with AES and remove the PKCS#7 padding, the AES variant is chosen by
+/// the length of `key`. `data` is left decrypted when the padding is invalid.
+pub fn cbc_decrypt_padded_in_place(
+    key: impl AsRef<[u8]>,
+    iv: impl AsRef<[u8]>,
+    data: &mut Vec<u8>,
+) -> Result<(), CipherError> {
+    let decryptor = Aes::new_checked(key.as_ref())?;
+    cbc_decrypt_padded_in_place_with(&decryptor, iv, data)
+}
+
+/// Decrypt `data` in place with any block cipher and remove the PKCS#7 padding
+pub fn cbc_decrypt_padded_in_place_with<C: BlockDecrypt>(
+    decryptor: &C,
+    iv: impl AsRef<[u8]>,
+    data: &mut Vec<u8>,
+) -> Result<(), CipherError> {
+    cbc_decrypt_in_place_with(decryptor, iv, data)?;
+    Ok(pkcs::unpad_in_place(data, C::block_size() as u8)?)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use base64::Engine;
-    use std::fs::File;
-    use std::io::{BufRead, BufReader};
+    use crate::base64;
+    use crate::block::Toy;
+    use crate::hex::Hex;
+    use aes::cipher::KeyInit;
+    use std::fs;
+    use yare::parameterized;
+
+    const KEY: &[u8; 16] = b"YELLOW SUBMARINE";
+    const IV: [u8; 16] = [0; 16];
 
     #[test]
     fn yellow_sub() {
-        let file = File::open("tests/assets/10.txt").unwrap();
-        let base_64 = BufReader::new(file)
-            .lines()
-            .fold(String::new(), |mut acc, e| {
-                acc.push_str(&e.unwrap());
-                acc
-            });
-        let bytes = base64::engine::general_purpose::STANDARD
-            .decode(base_64.as_bytes())
-            .unwrap();
-        let decrypted = cbc_decrypt("YELLOW SUBMARINE", &[0u8; 16], &bytes);
+        let base_64 = fs::read_to_string("tests/assets/10.txt").unwrap();
+        let bytes = base64::decode(base_64).unwrap();
+        let decrypted = cbc_decrypt("YELLOW SUBMARINE", [0u8; 16], &bytes).unwrap();
         let plaintext = String::from_utf8(decrypted).unwrap();
         assert!(plaintext.starts_with("I'm back and I'm ringin' the bell"))
     }
 
     #[test]
     fn re_encrypt() {
-        let file = File::open("tests/assets/10.txt").unwrap();
-        let base_64 = BufReader::new(file)
-            .lines()
-            .fold(String::new(), |mut acc, e| {
-                acc.push_str(&e.unwrap());
-                acc
-            });
-        let bytes = base64::engine::general_purpose::STANDARD
-            .decode(base_64.as_bytes())
+        let base_64 = fs::read_to_string("tests/assets/10.txt").unwrap();
+        let bytes = base64::decode(base_64).unwrap();
+        let decrypted = cbc_decrypt("YELLOW SUBMARINE", [0u8; 16], &bytes).unwrap();
+        let encrypted = encrypt("YELLOW SUBMARINE", [0u8; 16], &decrypted).unwrap();
+        assert_eq!(encrypted, bytes);
+    }
+
+    #[test]
+    fn aes_256() {
+        let key = Hex::try_from("603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4")
             .unwrap();
-        let decrypted = cbc_decrypt("YELLOW SUBMARINE", &[0u8; 16], &bytes);
-        let encrypted = encrypt("YELLOW SUBMARINE", &[0u8; 16], &decrypted);
+        let iv = Hex::try_from("000102030405060708090a0b0c0d0e0f").unwrap();
+        let plaintext = Hex::try_from("6bc1bee22e409f96e93d7e117393172a").unwrap();
+        let ciphertext = Hex::try_from("f58c4c04d6e5f1ba779eabfb5f7bfbd6").unwrap();
+        let key: &[u8] = (&key).into();
+        let iv: &[u8] = (&iv).into();
+
+        let encrypted = encrypt(key, iv, <&[u8]>::from(&plaintext)).unwrap();
+        assert_eq!(encrypted, <&[u8]>::from(&ciphertext));
+        assert_eq!(
+            cbc_decrypt(key, iv, encrypted).unwrap(),
+            <&[u8]>::from(&plaintext)
+        );
+    }
+
+    #[test]
+    fn toy_cipher() {
+        let toy = Toy::new_from_slice(b"toy key!").unwrap();
+        let raw = b"same 8b!same 8b!";
+        let encrypted = encrypt_with(&toy, b"an iv!!!", raw).unwrap();
+        assert_ne!(encrypted[..8], encrypted[8..]);
+        assert_eq!(cbc_decrypt_with(&toy, b"an iv!!!", encrypted).unwrap(), raw);
+    }
+
+    #[test]
+    fn invalid_key_length() {
+        assert_eq!(
+            encrypt("SHORT KEY", [0; 16], [0; 16]),
+            Err(CipherError::InvalidKeyLength(9))
+        );
+        assert_eq!(
+            cbc_decrypt("SHORT KEY", [0; 16], [0; 16]),
+            Err(CipherError::InvalidKeyLength(9))
+        );
+    }
+
+    #[test]
+    fn invalid_iv_length() {
+        let expected = Err(CipherError::InvalidIvLength {
+            expected: 16,
+            actual: 8,
+        });
+        assert_eq!(encrypt("YELLOW SUBMARINE", [0; 8], [0; 16]), expected);
+        assert_eq!(cbc_decrypt("YELLOW SUBMARINE", [0; 8], [0; 16]), expected);
+    }
+
+    #[test]
+    fn not_block_aligned() {
+        let expected = Err(CipherError::NotBlockAligned {
+            length: 20,
+            block_size: 16,
+        });
+        assert_eq!(encrypt("YELLOW SUBMARINE", [0; 16], [0; 20]), expected);
+        assert_eq!(cbc_decrypt("YELLOW SUBMARINE", [0; 16], [0; 20]), expected);
+    }
+
+    #[test]
+    fn padded_yellow_sub() {
+        let base_64 = fs::read_to_string("tests/assets/10.txt").unwrap();
+        let bytes = base64::decode(base_64).unwrap();
+        let decrypted = cbc_decrypt_padded("YELLOW SUBMARINE", [0u8; 16], &bytes).unwrap();
+        assert!(decrypted.ends_with(b"Play that funky music \n"));
+        let encrypted = encrypt_padded("YELLOW SUBMARINE", [0u8; 16], &decrypted).unwrap();
         assert_eq!(encrypted, bytes);
     }
+
+    #[test]
+    fn padded_toy_cipher() {
+        let toy = Toy::new_from_slice(b"toy key!").unwrap();
+        let encrypted = encrypt_padded_with(&toy, b"an iv!!!", b"12 bytes!!!!").unwrap();
+        assert_eq!(encrypted.len(), 16);
+        assert_eq!(
+            cbc_decrypt_padded_with(&toy, b"an iv!!!", encrypted).unwrap(),
+            b"12 bytes!!!!"
+        );
+    }
+
+    #[test]
+    fn decrypt_padded_bad_padding() {
+        let encrypted = encrypt("YELLOW SUBMARINE", [0; 16], b"ends in a five\x05\x05").unwrap();
+        assert_eq!(
+            cbc_decrypt_padded("YELLOW SUBMARINE", [0; 16], encrypted),
+            Err(CipherError::Padding(pkcs::PaddingError::Inconsistent(5)))
+        );
+    }
+
+    #[test]
+    fn in_place_matches_copy() {
+        let base_64 = fs::read_to_string("tests/assets/10.txt").unwrap();
+        let bytes = base64::decode(base_64).unwrap();
+        let mut data = bytes.clone();
+        cbc_decrypt_in_place(KEY, IV, &mut data).unwrap();
+        assert_eq!(data, cbc_decrypt(KEY, IV, &bytes).unwrap());
+        encrypt_in_place(KEY, IV, &mut data).unwrap();
+        assert_eq!(data, bytes);
+    }
+
+    #[test]
+    fn padded_in_place_uses_spare_capacity() {
+        let toy = Toy::new_from_slice(b"toy key!").unwrap();
+        let mut data = Vec::with_capacity(16);
+        data.extend_from_slice(b"12 bytes!!!!");
+        let pointer = data.as_ptr();
+        encrypt_padded_in_place_with(&toy, b"an iv!!!", &mut data).unwrap();
+        assert_eq!(
+            data,
+            encrypt_padded_with(&toy, b"an iv!!!", b"12 bytes!!!!").unwrap()
+        );
+        assert_eq!(data.as_ptr(), pointer);
+        cbc_decrypt_padded_in_place_with(&toy, b"an iv!!!", &mut data).unwrap();
+        assert_eq!(data, b"12 bytes!!!!");
+    }
+
+    #[test]
+    fn in_place_not_block_aligned() {
+        let expected = Err(CipherError::NotBlockAligned {
+            length: 20,
+            block_size: 16,
+        });
+        assert_eq!(encrypt_in_place(KEY, IV, &mut [0; 20]), expected);
+        assert_eq!(cbc_decrypt_in_place(KEY, IV, &mut [0; 20]), expected);
+        assert_eq!(
+            cbc_decrypt_padded_in_place(KEY, IV, &mut vec![0; 20]),
+            expected
+        );
+    }
+
+    /// One block at a time, for checking the multi block decryption
+    fn serial_decrypt<C: BlockDecrypt>(decryptor: &C, iv: &[u8], encrypted: &[u8]) -> Vec<u8> {
+        let mut previous = iv;
+        let mut output = vec![];
+        for block in encrypted.chunks_exact(C::block_size()) {
+            let mut decrypted = Block::<C>::clone_from_slice(block);
+            decryptor.decrypt_block(&mut decrypted);
+            decrypted.xor(Block::<C>::from_slice(previous));
+            output.extend(decrypted);
+            previous = block;
+        }
+        output
+    }
+
+    #[parameterized(
+    empty = { 0 },
+    one = { 1 },
+    under_group = { PARALLEL_BLOCKS - 1 },
+    group = { PARALLEL_BLOCKS },
+    over_group = { PARALLEL_BLOCKS + 1 },
+    several_groups = { PARALLEL_BLOCKS * 3 + 7 },
+    )]
+    fn multi_block_decrypt_matches_serial(blocks: usize) {
+        let encrypted = (0..blocks * 16)
+            .map(|i| (i * 7 % 256) as u8)
+            .collect::<Vec<_>>();
+        let aes = Aes::new_checked(KEY).unwrap();
+        let expected = serial_decrypt(&aes, &IV, &encrypted);
+        assert_eq!(cbc_decrypt_with(&aes, IV, &encrypted).unwrap(), expected);
+        let mut in_place = encrypted.clone();
+        cbc_decrypt_in_place_with(&aes, IV, &mut in_place).unwrap();
+        assert_eq!(in_place, expected);
+
+        let toy = Toy::new_from_slice(b"toy key!").unwrap();
+        let expected = serial_decrypt(&toy, b"an iv!!!", &encrypted);
+        assert_eq!(
+            cbc_decrypt_with(&toy, b"an iv!!!", &encrypted).unwrap(),
+            expected
+        );
+        let mut in_place = encrypted.clone();
+        cbc_decrypt_in_place_with(&toy, b"an iv!!!", &mut in_place).unwrap();
+        assert_eq!(in_place, expected);
+    }
 }
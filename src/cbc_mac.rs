@@ -0,0 +1,353 @@
+//          Copyright Nick G 2023.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! CBC-MAC and its forgeries, https://cryptopals.com/sets/7/challenges/49 and
+//! https://cryptopals.com/sets/7/challenges/50
+//!
+//! The MAC is the last block of the PKCS#7 padded message encrypted with CBC. Being CBC, an
+//! attacker controlled IV can undo changes to the first block, and the tag of one message is the
+//! chaining value needed to splice a second message on the end of it. Used as a hash with a known
+//! key, any message can be given any hash by a single glue block.
+
+use crate::block::{Aes, CipherError};
+use crate::{cbc, pkcs};
+use aes::cipher::BlockEncrypt;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// Errors from the CBC-MAC forgeries
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CbcMacError {
+    Cipher(CipherError),
+    /// The forged message must be the same length as the original
+    LengthMismatch {
+        original: usize,
+        forged: usize,
+    },
+    /// The IV can only make up for changes to the first block, the first byte changed after it
+    ChangedAfterFirstBlock(usize),
+    /// The tag must be exactly one block long
+    InvalidTagLength {
+        expected: usize,
+        actual: usize,
+    },
+    /// The message being spliced on must have at least one whole block for the glue
+    MessageTooShort {
+        length: usize,
+        block_size: usize,
+    },
+}
+
+impl Display for CbcMacError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CbcMacError::Cipher(error) => write!(f, "{error}"),
+            CbcMacError::LengthMismatch { original, forged } => write!(
+                f,
+                "forged length ({forged}) does not match original length ({original})"
+            ),
+            CbcMacError::ChangedAfterFirstBlock(index) => {
+                write!(f, "byte {index} is changed, past the first block")
+            }
+            CbcMacError::InvalidTagLength { expected, actual } => {
+                write!(f, "invalid tag length ({actual}), expected {expected}")
+            }
+            CbcMacError::MessageTooShort { length, block_size } => write!(
+                f,
+                "message length ({length}) is shorter than the block size ({block_size})"
+            ),
+        }
+    }
+}
+
+impl Error for CbcMacError {}
+
+impl From<CipherError> for CbcMacError {
+    fn from(error: CipherError) -> Self {
+        CbcMacError::Cipher(error)
+    }
+}
+
+/// The CBC-MAC of `message` with AES, the AES variant is chosen by the length of `key`
+pub fn mac(
+    key: impl AsRef<[u8]>,
+    iv: impl AsRef<[u8]>,
+    message: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CipherError> {
+    let encryptor = Aes::new_checked(key.as_ref())?;
+    mac_with(&encryptor, iv, message)
+}
+
+/// The CBC-MAC of `message` with any block cipher
+pub fn mac_with<C: BlockEncrypt>(
+    encryptor: &C,
+    iv: impl AsRef<[u8]>,
+    message: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CipherError> {
+    let mut encrypted = cbc::encrypt_padded_with(encryptor, iv, message)?;
+    Ok(encrypted.split_off(encrypted.len() - C::block_size()))
+}
+
+/// The CBC-MAC of `message` with AES and an IV of zeros
+pub fn mac_zero_iv(
+    key: impl AsRef<[u8]>,
+    message: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CipherError> {
+    mac(key, [0; 16], message)
+}
+
+/// Whether `tag` is the CBC-MAC of `message`, comparing every byte regardless of where the first
+/// difference is
+pub fn verify(
+    key: impl AsRef<[u8]>,
+    iv: impl AsRef<[u8]>,
+    message: impl AsRef<[u8]>,
+    tag: impl AsRef<[u8]>,
+) -> Result<bool, CipherError> {
+    let expected = mac(key, iv, message)?;
+    let tag = tag.as_ref();
+    let difference = expected
+        .iter()
+        .zip(tag)
+        .fold(0, |difference, (a, b)| difference | (a ^ b));
+    Ok(expected.len() == tag.len() && difference == 0)
+}
+
+/// The IV which gives `forged` the same MAC as `original` had with `iv`.
+///
+/// Only the first block may differ, which is where the IV is mixed in. This is the forgery for
+/// schemes which send the IV along with the message.
+pub fn forge_iv(
+    iv: impl AsRef<[u8]>,
+    original: impl AsRef<[u8]>,
+    forged: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CbcMacError> {
+    let iv = iv.as_ref();
+    let original = original.as_ref();
+    let forged = forged.as_ref();
+    if original.len() != forged.len() {
+        return Err(CbcMacError::LengthMismatch {
+            original: original.len(),
+            forged: forged.len(),
+        });
+    }
+    let changed = original.iter().zip(forged).position(|(o, f)| o != f);
+    if let Some(index) = changed.filter(|index| *index >= iv.len()) {
+        return Err(CbcMacError::ChangedAfterFirstBlock(index));
+    }
+    Ok(iv
+        .iter()
+        .enumerate()
+        .map(|(i, v)| v ^ original.get(i).unwrap_or(&0) ^ forged.get(i).unwrap_or(&0))
+        .collect())
+}
+
+/// Splice `extension` onto `message`, where `tag` is the MAC of `message` and both messages were
+/// MACed with `iv`.
+///
+/// The forged message is the padded `message`, then the first block of `extension` XORed with
+/// `tag` and `iv`, then the rest of `extension`, and has the same MAC as `extension`.
+pub fn extend(
+    iv: impl AsRef<[u8]>,
+    message: impl AsRef<[u8]>,
+    tag: impl AsRef<[u8]>,
+    extension: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CbcMacError> {
+    let iv = iv.as_ref();
+    let tag = tag.as_ref();
+    let block_size = iv.len();
+    if tag.len() != block_size {
+        return Err(CbcMacError::InvalidTagLength {
+            expected: block_size,
+            actual: tag.len(),
+        });
+    }
+    let mut forged = pkcs::pad(message, block_size as u8);
+    forged.extend(glue(iv, tag, extension.as_ref())?);
+    Ok(forged)
+}
+
+/// Forge a message starting with `prefix` which has the same MAC as `target`, for CBC-MAC used as
+/// a hash with a known `key`.
+///
+/// The padded `prefix` is followed by a glue block and then the rest of `target`. The padding
+/// and glue block are arbitrary bytes, so the prefix should end somewhere they are ignored, like
+/// a line comment. Digits are added to the end of `prefix` until the padding and glue block have
+/// no line terminators, `\n`, `\r`, U+2028 or U+2029, which would end the comment.
+pub fn collide(
+    key: impl AsRef<[u8]>,
+    iv: impl AsRef<[u8]>,
+    target: impl AsRef<[u8]>,
+    prefix: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CbcMacError> {
+    let key = key.as_ref();
+    let iv = iv.as_ref();
+    let target = target.as_ref();
+    let prefix = prefix.as_ref();
+    for attempt in 0u64.. {
+        let varied = match attempt {
+            0 => prefix.to_vec(),
+            _ => [prefix, attempt.to_string().as_bytes()].concat(),
+        };
+        let chained = mac(key, iv, &varied)?;
+        let mut forged = pkcs::pad(varied, iv.len() as u8);
+        let glue_end = forged.len() + iv.len();
+        forged.extend(glue(iv, &chained, target)?);
+        // Two bytes past the glue in case it ends in the start of U+2028 or U+2029
+        let end = forged.len().min(glue_end + 2);
+        if !has_line_terminator(&forged[prefix.len()..end]) {
+            return Ok(forged);
+        }
+    }
+    unreachable!("a prefix is found long before the attempts run out")
+}
+
+/// Whether `bytes` has a JavaScript line terminator, `\n`, `\r`, or U+2028 or U+2029 in UTF-8
+fn has_line_terminator(bytes: &[u8]) -> bool {
+    bytes.iter().any(|b| *b == b'\n' || *b == b'\r')
+        || bytes
+            .windows(3)
+            .any(|w| w == "\u{2028}".as_bytes() || w == "\u{2029}".as_bytes())
+}
+
+/// `message` with `iv ^ chained` XORed into its first block, so that it MACs the same after a
+/// chaining value of `chained` as it did after `iv`. A message shorter than a block can't be
+/// glued, the padding added when it is MACed would not be XORed.
+fn glue(iv: &[u8], chained: &[u8], message: &[u8]) -> Result<Vec<u8>, CbcMacError> {
+    if message.len() < iv.len() {
+        return Err(CbcMacError::MessageTooShort {
+            length: message.len(),
+            block_size: iv.len(),
+        });
+    }
+    let mut glued = message.to_vec();
+    for (byte, (v, c)) in glued.iter_mut().zip(iv.iter().zip(chained)) {
+        *byte ^= v ^ c;
+    }
+    Ok(glued)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::Toy;
+    use crate::hex::Hex;
+    use aes::cipher::KeyInit;
+    use yare::parameterized;
+
+    const KEY: &[u8; 16] = b"YELLOW SUBMARINE";
+    const IV: &[u8; 16] = b"an initial value";
+
+    #[test]
+    fn mac_is_last_block() {
+        let message = b"from=1&to=2&amount=100";
+        let encrypted = cbc::encrypt_padded(KEY, IV, message).unwrap();
+        let tag = mac(KEY, IV, message).unwrap();
+        assert_eq!(tag, &encrypted[16..]);
+        assert!(verify(KEY, IV, message, &tag).unwrap());
+        assert!(!verify(KEY, IV, b"from=1&to=2&amount=900", &tag).unwrap());
+        assert!(!verify(KEY, IV, message, &tag[..15]).unwrap());
+        assert_eq!(mac_zero_iv(KEY, message), mac(KEY, [0; 16], message));
+    }
+
+    #[test]
+    fn toy_cipher() {
+        let toy = Toy::new_from_slice(b"toy key!").unwrap();
+        let tag = mac_with(&toy, [0; 8], b"message").unwrap();
+        assert_eq!(tag.len(), 8);
+    }
+
+    #[test]
+    fn attacker_controlled_iv() {
+        let original = b"from=0007&to=0007&amount=1000000";
+        let tag = mac(KEY, IV, original).unwrap();
+        let forged = b"from=0001&to=0007&amount=1000000";
+        let iv = forge_iv(IV, original, forged).unwrap();
+        assert!(verify(KEY, &iv, forged, &tag).unwrap());
+    }
+
+    #[test]
+    fn forge_iv_errors() {
+        assert_eq!(
+            forge_iv(IV, b"abc", b"abcd"),
+            Err(CbcMacError::LengthMismatch {
+                original: 3,
+                forged: 4
+            })
+        );
+        let original = b"from=0007&to=0007&amount=1000000";
+        let forged = b"from=0007&to=0001&amount=1000000";
+        assert_eq!(
+            forge_iv(IV, original, forged),
+            Err(CbcMacError::ChangedAfterFirstBlock(16))
+        );
+    }
+
+    #[test]
+    fn length_extension() {
+        let victim = b"from=0001&tx_list=0002:10;0003:20";
+        let victim_tag = mac_zero_iv(KEY, victim).unwrap();
+        let attacker = b"from=0007&tx_list=0004:1;0007:1000000";
+        let attacker_tag = mac_zero_iv(KEY, attacker).unwrap();
+
+        let forged = extend([0; 16], victim, &victim_tag, attacker).unwrap();
+        assert!(forged.starts_with(victim));
+        assert!(forged.ends_with(b";0007:1000000"));
+        assert!(verify(KEY, [0; 16], &forged, &attacker_tag).unwrap());
+    }
+
+    #[test]
+    fn length_extension_with_iv() {
+        let first = b"first message";
+        let second = b"second message which is longer";
+        let forged = extend(IV, first, mac(KEY, IV, first).unwrap(), second).unwrap();
+        assert_eq!(mac(KEY, IV, forged), mac(KEY, IV, second));
+        assert_eq!(
+            extend(IV, first, [0; 8], second),
+            Err(CbcMacError::InvalidTagLength {
+                expected: 16,
+                actual: 8
+            })
+        );
+        assert_eq!(
+            extend(IV, first, [0; 16], b"short"),
+            Err(CbcMacError::MessageTooShort {
+                length: 5,
+                block_size: 16
+            })
+        );
+    }
+
+    #[test]
+    fn javascript_collision() {
+        let target = b"alert('MZA who was that?');\n";
+        let hash = mac_zero_iv(KEY, target).unwrap();
+        assert_eq!(
+            String::from(&Hex::from(hash.as_slice())),
+            "296b8d7cb78a243dda4d0a61d33bbdd1"
+        );
+
+        let prefix = b"alert('Ayo, the Wu is back!');//";
+        let forged = collide(KEY, [0; 16], target, prefix).unwrap();
+        assert!(forged.starts_with(prefix));
+        assert!(forged.ends_with(&target[16..]));
+        assert!(has_line_terminator("a\u{2029}b".as_bytes()));
+        assert_eq!(mac_zero_iv(KEY, &forged).unwrap(), hash);
+    }
+
+    #[parameterized(
+    full_block_padding = { b"alert('Ayo, the Wu is back!');//" },
+    newline_padding = { b"alert('Ayo, Wu!!!');//" },
+    carriage_return_padding = { b"alert('Ayo Wu!');//" },
+    )]
+    fn collision_keeps_comment(prefix: &[u8]) {
+        let target = b"alert('MZA who was that?');\n";
+        let forged = collide(KEY, [0; 16], target, prefix).unwrap();
+        assert!(forged.starts_with(prefix));
+        assert_eq!(mac_zero_iv(KEY, &forged), mac_zero_iv(KEY, target));
+        let comment = &forged[prefix.len()..forged.len() - (target.len() - 16)];
+        assert!(!has_line_terminator(comment), "{comment:?}");
+    }
+}
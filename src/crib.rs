@@ -0,0 +1,373 @@
+//          Copyright Nick G 2023.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! Crib dragging, using a guessed piece of plaintext to recover XOR keystream.
+//!
+//! Sliding a crib across a ciphertext reveals the key at each position, which will look like text
+//! if the key is a passphrase. Sliding a crib across the XOR of two ciphertexts which share a
+//! keystream, `Hex` supports `c1 ^ c2`, reveals the other plaintext. Once a crib is placed the
+//! keystream bytes can be locked into a [`KnownKeystream`] and built up a piece at a time.
+
+use crate::hex::Hex;
+use crate::score::Scorer;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// The byte shown in a partial decryption for an unknown keystream byte
+pub const UNKNOWN: u8 = b'_';
+
+/// Errors from locking keystream bytes
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CribError {
+    /// The crib extends past the end of the ciphertext
+    OutOfRange { offset: usize, length: usize },
+    /// The end of the `length` bytes starting at `offset` is past `usize::MAX`
+    Overflow { offset: usize, length: usize },
+    /// The keystream byte at `offset` was already locked to a different value
+    Conflict {
+        offset: usize,
+        locked: u8,
+        requested: u8,
+    },
+}
+
+impl Display for CribError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            CribError::OutOfRange { offset, length } => write!(
+                f,
+                "crib at offset {offset} extends past the ciphertext length {length}"
+            ),
+            CribError::Overflow { offset, length } => {
+                write!(f, "{length} bytes at offset {offset} overflow")
+            }
+            CribError::Conflict {
+                offset,
+                locked,
+                requested,
+            } => write!(
+                f,
+                "keystream byte {offset} is locked to {locked:#04x}, not {requested:#04x}"
+            ),
+        }
+    }
+}
+
+impl Error for CribError {}
+
+/// The crib placed at one offset
+#[derive(Clone, Debug, PartialEq)]
+pub struct CribMatch {
+    /// Where the crib starts
+    pub offset: usize,
+    /// The score of the revealed bytes, higher is more likely
+    pub score: f32,
+    /// The bytes under the crib XORed with the crib
+    pub revealed: Vec<u8>,
+}
+
+/// Slide `crib` across every offset of `target` and rank the offsets by how plausible the
+/// revealed bytes are, best first.
+///
+/// `target` is either a ciphertext, revealing the key, or the XOR of two ciphertexts encrypted
+/// with the same keystream, revealing the other plaintext.
+pub fn drag(target: &Hex, crib: &[u8], scorer: &(impl Scorer + ?Sized)) -> Vec<CribMatch> {
+    let bytes: &[u8] = target.into();
+    if crib.is_empty() || crib.len() > bytes.len() {
+        return vec![];
+    }
+    let crib = Hex::from(crib);
+    let mut matches = bytes
+        .windows(<&[u8]>::from(&crib).len())
+        .enumerate()
+        .map(|(offset, window)| {
+            let revealed = Hex::from(window) ^ crib.clone();
+            let revealed = <&[u8]>::from(&revealed).to_vec();
+            CribMatch {
+                offset,
+                score: scorer.score(&revealed),
+                revealed,
+            }
+        })
+        .collect::<Vec<_>>();
+    matches.sort_by(|x, y| y.score.total_cmp(&x.score));
+    matches
+}
+
+/// Keystream bytes which have been recovered so far
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct KnownKeystream {
+    /// The locked bytes by index, sparse so that bytes can be locked at any offset
+    locked: BTreeMap<usize, u8>,
+    period: Option<usize>,
+}
+
+impl KnownKeystream {
+    /// A keystream which never repeats, like CTR mode
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A repeating key of `key_size` bytes, locked bytes apply at every multiple of `key_size`
+    pub fn repeating(key_size: usize) -> Self {
+        assert!(key_size > 0, "the key size must be at least one byte");
+        Self {
+            locked: BTreeMap::new(),
+            period: Some(key_size),
+        }
+    }
+
+    /// The first `length` bytes of the keystream, `None` for bytes which are not known yet
+    pub fn keystream(&self, length: usize) -> Vec<Option<u8>> {
+        (0..length).map(|offset| self.get(offset)).collect()
+    }
+
+    /// The keystream byte used at `offset` of a ciphertext
+    pub fn get(&self, offset: usize) -> Option<u8> {
+        self.locked.get(&self.index(offset)).copied()
+    }
+
+    /// Lock the keystream bytes revealed by `crib` being the plaintext at `offset` of
+    /// `ciphertext`. Nothing is locked if any byte conflicts with an already locked byte.
+    pub fn lock(
+        &mut self,
+        ciphertext: impl AsRef<[u8]>,
+        offset: usize,
+        crib: impl AsRef<[u8]>,
+    ) -> Result<(), CribError> {
+        let ciphertext = ciphertext.as_ref();
+        let crib = crib.as_ref();
+        let under_crib = offset
+            .checked_add(crib.len())
+            .and_then(|end| ciphertext.get(offset..end))
+            .ok_or(CribError::OutOfRange {
+                offset,
+                length: ciphertext.len(),
+            })?;
+        let keystream = Hex::from(under_crib) ^ Hex::from(crib);
+        self.lock_keystream(offset, <&[u8]>::from(&keystream))
+    }
+
+    /// Lock `keystream` bytes starting at `offset`. Nothing is locked if any byte conflicts with
+    /// an already locked byte.
+    pub fn lock_keystream(
+        &mut self,
+        offset: usize,
+        keystream: impl AsRef<[u8]>,
+    ) -> Result<(), CribError> {
+        let keystream = keystream.as_ref();
+        let end = checked_end(offset, keystream.len())?;
+        let mut updated = self.locked.clone();
+        for (position, requested) in (offset..end).zip(keystream.iter().copied()) {
+            match updated.insert(self.index(position), requested) {
+                Some(locked) if locked != requested => {
+                    return Err(CribError::Conflict {
+                        offset: position,
+                        locked,
+                        requested,
+                    })
+                }
+                _ => {}
+            }
+        }
+        self.locked = updated;
+        Ok(())
+    }
+
+    /// Forget the keystream bytes for `length` bytes starting at `offset`
+    pub fn unlock(&mut self, offset: usize, length: usize) -> Result<(), CribError> {
+        let end = checked_end(offset, length)?;
+        match self.period {
+            // Past one period every index has been visited
+            Some(period) => {
+                for position in (offset..end).take(period) {
+                    self.locked.remove(&(position % period));
+                }
+            }
+            None => {
+                let mut unlocked = self.locked.split_off(&offset);
+                self.locked.append(&mut unlocked.split_off(&end));
+            }
+        }
+        Ok(())
+    }
+
+    /// Decrypt as much of `ciphertext` as possible, `None` where the keystream is unknown
+    pub fn decrypt(&self, ciphertext: impl AsRef<[u8]>) -> Vec<Option<u8>> {
+        ciphertext
+            .as_ref()
+            .iter()
+            .enumerate()
+            .map(|(offset, c)| self.get(offset).map(|k| c ^ k))
+            .collect()
+    }
+
+    /// Decrypt as much of `ciphertext` as possible, showing unknown bytes as [`UNKNOWN`]
+    pub fn partial_decrypt(&self, ciphertext: impl AsRef<[u8]>) -> Vec<u8> {
+        self.decrypt(ciphertext)
+            .into_iter()
+            .map(|byte| byte.unwrap_or(UNKNOWN))
+            .collect()
+    }
+
+    fn index(&self, offset: usize) -> usize {
+        match self.period {
+            Some(period) => offset % period,
+            None => offset,
+        }
+    }
+}
+
+fn checked_end(offset: usize, length: usize) -> Result<usize, CribError> {
+    offset
+        .checked_add(length)
+        .ok_or(CribError::Overflow { offset, length })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ctr;
+    use crate::score::{NgramLogLikelihood, PrintablePenalty};
+    use crate::xor;
+
+    const KEY: &[u8; 16] = b"YELLOW SUBMARINE";
+
+    fn english() -> (NgramLogLikelihood, PrintablePenalty) {
+        (
+            NgramLogLikelihood::english_bigrams(),
+            PrintablePenalty::default(),
+        )
+    }
+
+    #[test]
+    fn drag_two_time_pad() {
+        let first = b"the attack begins at dawn, bring the ladders";
+        let second = b"send more supplies to the northern outpost!!";
+        let c1 = ctr::encrypt(KEY, [0; 8], first).unwrap();
+        let c2 = ctr::encrypt(KEY, [0; 8], second).unwrap();
+        let xored = Hex::from(c1.as_slice()) ^ Hex::from(c2.as_slice());
+
+        let matches = drag(&xored, b" the ", &english());
+        assert_eq!(matches.len(), first.len() - 4);
+        assert!(matches.windows(2).all(|m| m[0].score >= m[1].score));
+        let top = matches.iter().take(3).collect::<Vec<_>>();
+        assert!(
+            top.iter()
+                .any(|m| second[m.offset..].starts_with(&m.revealed)),
+            "{top:?}"
+        );
+    }
+
+    #[test]
+    fn drag_reveals_key() {
+        let plaintext = b"GIF89a followed by a lot of image data which is not text";
+        let cipher = xor::xor_encrypt(plaintext, b"open sesame");
+        let matches = drag(&cipher, b"GIF89a", &english());
+        assert_eq!(matches[0].offset, 0);
+        assert_eq!(matches[0].revealed, b"open s");
+    }
+
+    #[test]
+    fn crib_longer_than_target() {
+        assert_eq!(
+            drag(&Hex::from(b"abc".as_slice()), b"abcd", &english()),
+            vec![]
+        );
+        assert_eq!(drag(&Hex::from(b"abc".as_slice()), b"", &english()), vec![]);
+    }
+
+    #[test]
+    fn lock_incrementally() {
+        let plaintext = b"attack at dawn";
+        let ciphertext = ctr::encrypt(KEY, [0; 8], plaintext).unwrap();
+        let mut known = KnownKeystream::new();
+        assert_eq!(known.partial_decrypt(&ciphertext), b"______________");
+
+        known.lock(&ciphertext, 0, b"attack").unwrap();
+        assert_eq!(known.partial_decrypt(&ciphertext), b"attack________");
+        known.lock(&ciphertext, 10, b"dawn").unwrap();
+        assert_eq!(known.partial_decrypt(&ciphertext), b"attack____dawn");
+        known.lock(&ciphertext, 6, b" at ").unwrap();
+        assert_eq!(known.partial_decrypt(&ciphertext), plaintext);
+
+        known.unlock(7, 2).unwrap();
+        assert_eq!(known.partial_decrypt(&ciphertext), b"attack __ dawn");
+    }
+
+    #[test]
+    fn lock_conflict_changes_nothing() {
+        let ciphertext = ctr::encrypt(KEY, [0; 8], b"attack at dawn").unwrap();
+        let mut known = KnownKeystream::new();
+        known.lock(&ciphertext, 0, b"attack").unwrap();
+        let before = known.clone();
+        assert_eq!(
+            known.lock(&ciphertext, 4, b"cxxx"),
+            Err(CribError::Conflict {
+                offset: 5,
+                locked: ciphertext[5] ^ b'k',
+                requested: ciphertext[5] ^ b'x',
+            })
+        );
+        assert_eq!(known, before);
+    }
+
+    #[test]
+    fn lock_out_of_range() {
+        let mut known = KnownKeystream::new();
+        assert_eq!(
+            known.lock(b"short", 3, b"long crib"),
+            Err(CribError::OutOfRange {
+                offset: 3,
+                length: 5
+            })
+        );
+        assert_eq!(
+            known.lock(b"short", usize::MAX, b"crib"),
+            Err(CribError::OutOfRange {
+                offset: usize::MAX,
+                length: 5
+            })
+        );
+        let overflow = Err(CribError::Overflow {
+            offset: usize::MAX - 1,
+            length: 2,
+        });
+        assert_eq!(known.lock_keystream(usize::MAX - 1, b"ab"), overflow);
+        assert_eq!(known.unlock(usize::MAX - 1, 2), overflow);
+        assert_eq!(known, KnownKeystream::new());
+        known.unlock(0, usize::MAX).unwrap();
+    }
+
+    #[test]
+    fn lock_far_offset() {
+        let mut known = KnownKeystream::new();
+        known.lock_keystream(1 << 40, b"ab").unwrap();
+        assert_eq!(known.get(1 << 40), Some(b'a'));
+        assert_eq!(known.get((1 << 40) + 1), Some(b'b'));
+        assert_eq!(known.keystream(2), [None, None]);
+        known
+            .unlock((1 << 40) + 1, usize::MAX - (1 << 40) - 1)
+            .unwrap();
+        assert_eq!(known.get(1 << 40), Some(b'a'));
+        assert_eq!(known.get((1 << 40) + 1), None);
+    }
+
+    #[test]
+    fn repeating_key() {
+        let plaintext = b"Burning 'em, if you ain't quick and nimble";
+        let cipher = xor::xor_encrypt(plaintext, b"ICE");
+        let cipher: &[u8] = (&cipher).into();
+        let mut known = KnownKeystream::repeating(3);
+        known.lock(cipher, 0, b"Bu").unwrap();
+        assert_eq!(known.keystream(3), [Some(b'I'), Some(b'C'), None]);
+        assert_eq!(&known.partial_decrypt(cipher)[..9], b"Bu_ni_g _");
+        known.lock(cipher, 5, b"n").unwrap();
+        assert_eq!(known.partial_decrypt(cipher), plaintext);
+        known.unlock(1000, usize::MAX - 1000).unwrap();
+        assert_eq!(known.keystream(3), [None; 3]);
+    }
+}
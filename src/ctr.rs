@@ -0,0 +1,333 @@
+//          Copyright Nick G 2023.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! CTR mode, https://cryptopals.com/sets/3/challenges/18
+//!
+//! The keystream is the encryption of successive counter blocks, each made of the nonce followed
+//! by the counter. The [`CounterLayout`] controls the split between nonce and counter and the
+//! counter byte order.
+
+use crate::block::{Aes, CipherError};
+use aes::cipher::consts::U16;
+use aes::cipher::{Block, BlockEncrypt};
+
+const BLOCK_SIZE: usize = 16;
+
+/// How the nonce and counter are arranged in a counter block
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CounterLayout {
+    /// 64 bit nonce followed by a 64 bit little endian counter, as used by cryptopals
+    #[default]
+    LittleEndian64,
+    /// 64 bit nonce followed by a 64 bit big endian counter, as in NIST SP 800-38A
+    BigEndian64,
+    /// 96 bit nonce followed by a 32 bit big endian counter, as in RFC 3686 and GCM
+    Nonce96Counter32,
+}
+
+impl CounterLayout {
+    /// The number of nonce bytes in a counter block
+    pub fn nonce_length(&self) -> usize {
+        match self {
+            CounterLayout::LittleEndian64 | CounterLayout::BigEndian64 => 8,
+            CounterLayout::Nonce96Counter32 => 12,
+        }
+    }
+
+    /// The largest counter which fits in a counter block, the counter wraps to 0 after it
+    pub fn max_counter(&self) -> u64 {
+        match self {
+            CounterLayout::LittleEndian64 | CounterLayout::BigEndian64 => u64::MAX,
+            CounterLayout::Nonce96Counter32 => u32::MAX.into(),
+        }
+    }
+
+    /// `counter` must not be more than [`max_counter`](CounterLayout::max_counter)
+    fn counter_block(&self, nonce: &[u8], counter: u64) -> [u8; BLOCK_SIZE] {
+        let mut block = [0u8; BLOCK_SIZE];
+        let (nonce_bytes, counter_bytes) = block.split_at_mut(self.nonce_length());
+        nonce_bytes.copy_from_slice(nonce);
+        match self {
+            CounterLayout::LittleEndian64 => counter_bytes.copy_from_slice(&counter.to_le_bytes()),
+            CounterLayout::BigEndian64 => counter_bytes.copy_from_slice(&counter.to_be_bytes()),
+            CounterLayout::Nonce96Counter32 => {
+                let counter = u32::try_from(counter).expect("the counter fits in 32 bits");
+                counter_bytes.copy_from_slice(&counter.to_be_bytes())
+            }
+        }
+        block
+    }
+}
+
+/// A CTR mode keystream generator
+#[derive(Clone)]
+pub struct Ctr<C = Aes> {
+    cipher: C,
+    nonce: Vec<u8>,
+    layout: CounterLayout,
+    initial_counter: u64,
+}
+
+impl Ctr<Aes> {
+    /// CTR mode with AES, the AES variant is chosen by the length of `key`
+    pub fn new(
+        key: impl AsRef<[u8]>,
+        nonce: impl AsRef<[u8]>,
+        layout: CounterLayout,
+    ) -> Result<Self, CipherError> {
+        Self::with_cipher(Aes::new_checked(key.as_ref())?, nonce, layout)
+    }
+}
+
+impl<C: BlockEncrypt<BlockSize = U16>> Ctr<C> {
+    /// CTR mode with any 16 byte block cipher
+    pub fn with_cipher(
+        cipher: C,
+        nonce: impl AsRef<[u8]>,
+        layout: CounterLayout,
+    ) -> Result<Self, CipherError> {
+        let nonce = nonce.as_ref();
+        if nonce.len() != layout.nonce_length() {
+            return Err(CipherError::InvalidNonceLength {
+                expected: layout.nonce_length(),
+                actual: nonce.len(),
+            });
+        }
+        Ok(Self {
+            cipher,
+            nonce: nonce.to_vec(),
+            layout,
+            initial_counter: 0,
+        })
+    }
+
+    /// Start the counter at `counter` instead of 0, it must fit in the counter layout
+    pub fn starting_at(mut self, counter: u64) -> Result<Self, CipherError> {
+        let max = self.layout.max_counter();
+        if counter > max {
+            return Err(CipherError::CounterOutOfRange { counter, max });
+        }
+        self.initial_counter = counter;
+        Ok(self)
+    }
+
+    /// The keystream from the start
+    pub fn keystream(&self) -> Keystream<'_, C> {
+        self.keystream_at(0)
+    }
+
+    /// The keystream starting at byte `offset`
+    pub fn keystream_at(&self, offset: u64) -> Keystream<'_, C> {
+        let mut keystream = Keystream {
+            ctr: self,
+            block_index: 0,
+            block: self.keystream_block(0),
+            position: 0,
+        };
+        keystream.seek(offset);
+        keystream
+    }
+
+    /// Encrypt, or decrypt, `data` from the start of the keystream
+    pub fn apply_keystream(&self, data: impl AsRef<[u8]>) -> Vec<u8> {
+        self.apply_keystream_at(0, data)
+    }
+
+    /// Encrypt, or decrypt, `data` as if it were located at byte `offset` of the message
+    pub fn apply_keystream_at(&self, offset: u64, data: impl AsRef<[u8]>) -> Vec<u8> {
+        data.as_ref()
+            .iter()
+            .zip(self.keystream_at(offset))
+            .map(|(d, k)| d ^ k)
+            .collect()
+    }
+
+    fn keystream_block(&self, index: u64) -> [u8; BLOCK_SIZE] {
+        let counter = self.initial_counter.wrapping_add(index) & self.layout.max_counter();
+        let mut block = Block::<C>::from(self.layout.counter_block(&self.nonce, counter));
+        self.cipher.encrypt_block(&mut block);
+        block.into()
+    }
+}
+
+/// An endless iterator over the keystream bytes
+pub struct Keystream<'a, C> {
+    ctr: &'a Ctr<C>,
+    block_index: u64,
+    block: [u8; BLOCK_SIZE],
+    position: usize,
+}
+
+impl<C: BlockEncrypt<BlockSize = U16>> Keystream<'_, C> {
+    /// Move to byte `offset` of the keystream
+    pub fn seek(&mut self, offset: u64) {
+        let block_index = offset / BLOCK_SIZE as u64;
+        if block_index != self.block_index {
+            self.block_index = block_index;
+            self.block = self.ctr.keystream_block(block_index);
+        }
+        self.position = (offset % BLOCK_SIZE as u64) as usize;
+    }
+}
+
+impl<C: BlockEncrypt<BlockSize = U16>> Iterator for Keystream<'_, C> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position == BLOCK_SIZE {
+            self.block_index = self.block_index.wrapping_add(1);
+            self.block = self.ctr.keystream_block(self.block_index);
+            self.position = 0;
+        }
+        let byte = self.block[self.position];
+        self.position += 1;
+        Some(byte)
+    }
+}
+
+/// Encrypt `raw` with AES in CTR mode using the default little endian layout
+pub fn encrypt(
+    key: impl AsRef<[u8]>,
+    nonce: impl AsRef<[u8]>,
+    raw: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CipherError> {
+    Ok(Ctr::new(key, nonce, CounterLayout::default())?.apply_keystream(raw))
+}
+
+/// Decrypt `encrypted` with AES in CTR mode using the default little endian layout
+pub fn ctr_decrypt(
+    key: impl AsRef<[u8]>,
+    nonce: impl AsRef<[u8]>,
+    encrypted: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CipherError> {
+    encrypt(key, nonce, encrypted)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::base64;
+    use crate::hex::Hex;
+
+    const KEY: &str = "YELLOW SUBMARINE";
+
+    fn bytes(hex: &str) -> Vec<u8> {
+        let hex = Hex::try_from(hex).unwrap();
+        <&[u8]>::from(&hex).to_vec()
+    }
+
+    #[test]
+    fn challenge_18() {
+        let encrypted = base64::decode(
+            "L77na/nrFsKvynd6HzOoG7GHTLXsTVu9qvY/2syLXzhPweyyMTJULu/6/kXX0KSvoOLSFQ==",
+        )
+        .unwrap();
+        let decrypted = ctr_decrypt("YELLOW SUBMARINE", [0; 8], &encrypted).unwrap();
+        assert_eq!(
+            String::from_utf8(decrypted.clone()).unwrap(),
+            "Yo, VIP Let's kick it Ice, Ice, baby Ice, Ice, baby "
+        );
+        assert_eq!(
+            encrypt("YELLOW SUBMARINE", [0; 8], decrypted),
+            Ok(encrypted)
+        );
+    }
+
+    // NIST SP 800-38A F.5.1
+    #[test]
+    fn big_endian_64() {
+        let ctr = Ctr::new(
+            bytes("2b7e151628aed2a6abf7158809cf4f3c"),
+            bytes("f0f1f2f3f4f5f6f7"),
+            CounterLayout::BigEndian64,
+        )
+        .unwrap()
+        .starting_at(0xf8f9fafbfcfdfeff)
+        .unwrap();
+        let plaintext = bytes("6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51");
+        assert_eq!(
+            ctr.apply_keystream(plaintext),
+            bytes("874d6191b620e3261bef6864990db6ce9806f66b7970fdff8617187bb9fffdff")
+        );
+    }
+
+    // RFC 3686 test vector #2
+    #[test]
+    fn nonce_96_counter_32() {
+        let ctr = Ctr::new(
+            bytes("7e24067817fae0d743d6ce1f32539163"),
+            bytes("006cb6dbc0543b59da48d90b"),
+            CounterLayout::Nonce96Counter32,
+        )
+        .unwrap()
+        .starting_at(1)
+        .unwrap();
+        let plaintext = bytes("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+        assert_eq!(
+            ctr.apply_keystream(plaintext),
+            bytes("5104a106168a72d9790d41ee8edad388eb2e1efc46da57c8fce630df9141be28")
+        );
+    }
+
+    #[test]
+    fn counter_32_range() {
+        let ctr = Ctr::new(KEY, [0; 12], CounterLayout::Nonce96Counter32).unwrap();
+        assert!(matches!(
+            ctr.clone().starting_at(1 << 32),
+            Err(CipherError::CounterOutOfRange {
+                counter: 0x1_0000_0000,
+                max: 0xffff_ffff
+            })
+        ));
+
+        // The counter wraps within its 32 bits, leaving the nonce alone
+        let last = ctr.starting_at(u32::MAX.into()).unwrap();
+        let first = Ctr::new(KEY, [0; 12], CounterLayout::Nonce96Counter32).unwrap();
+        let wrapped = last.keystream().skip(16).take(16).collect::<Vec<_>>();
+        assert_eq!(wrapped, first.keystream().take(16).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn seek_matches_keystream() {
+        let ctr = Ctr::new("YELLOW SUBMARINE", [3; 8], CounterLayout::default()).unwrap();
+        let keystream = ctr.keystream().take(100).collect::<Vec<_>>();
+        for offset in [0, 1, 15, 16, 17, 63] {
+            let seeked = ctr.keystream_at(offset).take(20).collect::<Vec<_>>();
+            assert_eq!(seeked, keystream[offset as usize..offset as usize + 20]);
+        }
+
+        let mut iter = ctr.keystream();
+        iter.seek(40);
+        assert_eq!(iter.next(), Some(keystream[40]));
+        iter.seek(3);
+        assert_eq!(iter.next(), Some(keystream[3]));
+    }
+
+    #[test]
+    fn apply_keystream_at_offset() {
+        let ctr = Ctr::new("YELLOW SUBMARINE", [0; 8], CounterLayout::default()).unwrap();
+        let message = b"a message long enough to span a few blocks of keystream";
+        let encrypted = ctr.apply_keystream(message);
+        assert_eq!(ctr.apply_keystream_at(21, &encrypted[21..]), &message[21..]);
+    }
+
+    #[test]
+    fn invalid_nonce_length() {
+        assert!(matches!(
+            Ctr::new("YELLOW SUBMARINE", [0; 8], CounterLayout::Nonce96Counter32),
+            Err(CipherError::InvalidNonceLength {
+                expected: 12,
+                actual: 8
+            })
+        ));
+        assert_eq!(
+            encrypt("YELLOW SUBMARINE", [0; 4], b"abc"),
+            Err(CipherError::InvalidNonceLength {
+                expected: 8,
+                actual: 4
+            })
+        );
+    }
+}
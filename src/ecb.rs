@@ -3,98 +3,353 @@
 //    (See accompanying file LICENSE or copy at
 //          https://www.boost.org/LICENSE_1_0.txt)
 
-use aes::cipher::{Block, BlockDecrypt, BlockEncrypt, BlockSizeUser, KeyInit};
-use aes::Aes128;
-use base64::Engine;
+use crate::base64::{self, Base64Error};
+use crate::block::{blocks_b2b, blocks_in_place, check_aligned, Aes, CipherError};
+use crate::hex::{Hex, HexError};
+use crate::pkcs;
+use aes::cipher::{BlockDecrypt, BlockEncrypt, BlockSizeUser};
 use itertools::Itertools;
 use std::borrow::Borrow;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
 
-pub fn encrypt(key: impl AsRef<[u8]>, raw: impl AsRef<[u8]>) -> Vec<u8> {
+/// Encrypt `raw` with AES, the AES variant is chosen by the length of `key`
+pub fn encrypt(key: impl AsRef<[u8]>, raw: impl AsRef<[u8]>) -> Result<Vec<u8>, CipherError> {
+    let encryptor = Aes::new_checked(key.as_ref())?;
+    encrypt_with(&encryptor, raw)
+}
+
+/// Encrypt `raw` with any block cipher
+pub fn encrypt_with<C: BlockEncrypt>(
+    encryptor: &C,
+    raw: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CipherError> {
     let raw = raw.as_ref();
-    let encryptor = Aes128::new_from_slice(key.as_ref()).unwrap();
+    check_aligned::<C>(raw)?;
     let mut output = vec![0; raw.len()];
-    let in_blocks = raw
-        .chunks_exact(Aes128::block_size())
-        .map(Block::<Aes128>::from_slice);
-    let out_blocks = output
-        .chunks_exact_mut(Aes128::block_size())
-        .map(Block::<Aes128>::from_mut_slice);
-    in_blocks
-        .zip(out_blocks)
-        .for_each(|(in_block, out_block)| encryptor.encrypt_block_b2b(in_block, out_block));
-    output
-}
-
-fn ecb_decrypt(key: impl AsRef<[u8]>, encrypted: impl AsRef<[u8]>) -> Vec<u8> {
+    encryptor.encrypt_blocks_inout(blocks_b2b::<C>(raw, &mut output));
+    Ok(output)
+}
+
+/// Decrypt `encrypted` with AES, the AES variant is chosen by the length of `key`
+pub fn ecb_decrypt(
+    key: impl AsRef<[u8]>,
+    encrypted: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CipherError> {
+    let decryptor = Aes::new_checked(key.as_ref())?;
+    ecb_decrypt_with(&decryptor, encrypted)
+}
+
+/// Decrypt `encrypted` with any block cipher
+pub fn ecb_decrypt_with<C: BlockDecrypt>(
+    decryptor: &C,
+    encrypted: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CipherError> {
     let encrypted = encrypted.as_ref();
-    let decryptor = Aes128::new_from_slice(key.as_ref()).unwrap();
+    check_aligned::<C>(encrypted)?;
     let mut output = vec![0; encrypted.len()];
-    let in_blocks = encrypted
-        .chunks_exact(Aes128::block_size())
-        .map(Block::<Aes128>::from_slice);
-    let out_blocks = output
-        .chunks_exact_mut(Aes128::block_size())
-        .map(Block::<Aes128>::from_mut_slice);
-    in_blocks
-        .zip(out_blocks)
-        .for_each(|(in_block, out_block)| decryptor.decrypt_block_b2b(in_block, out_block));
-    output
-}
-
-fn detect_ecb(lines: impl IntoIterator<Item = impl Borrow<str>>) -> Option<usize> {
-    for (index, line) in lines.into_iter().enumerate() {
-        let line = line.borrow();
-        let bytes = base64::engine::general_purpose::STANDARD
-            .decode(line)
-            .unwrap();
-        let chunks = bytes.chunks_exact(Aes128::block_size());
-        if chunks.into_iter().duplicates().next().is_some() {
-            return Some(index);
+    decryptor.decrypt_blocks_inout(blocks_b2b::<C>(encrypted, &mut output));
+    Ok(output)
+}
+
+/// Encrypt `data` in place with AES, the AES variant is chosen by the length of `key`
+pub fn encrypt_in_place(key: impl AsRef<[u8]>, data: &mut [u8]) -> Result<(), CipherError> {
+    let encryptor = Aes::new_checked(key.as_ref())?;
+    encrypt_in_place_with(&encryptor, data)
+}
+
+/// Encrypt `data` in place with any block cipher
+pub fn encrypt_in_place_with<C: BlockEncrypt>(
+    encryptor: &C,
+    data: &mut [u8],
+) -> Result<(), CipherError> {
+    check_aligned::<C>(data)?;
+    encryptor.encrypt_blocks_inout(blocks_in_place::<C>(data));
+    Ok(())
+}
+
+/// Decrypt `data` in place with AES, the AES variant is chosen by the length of `key`
+pub fn ecb_decrypt_in_place(key: impl AsRef<[u8]>, data: &mut [u8]) -> Result<(), CipherError> {
+    let decryptor = Aes::new_checked(key.as_ref())?;
+    ecb_decrypt_in_place_with(&decryptor, data)
+}
+
+/// Decrypt `data` in place with any block cipher
+pub fn ecb_decrypt_in_place_with<C: BlockDecrypt>(
+    decryptor: &C,
+    data: &mut [u8],
+) -> Result<(), CipherError> {
+    check_aligned::<C>(data)?;
+    decryptor.decrypt_blocks_inout(blocks_in_place::<C>(data));
+    Ok(())
+}
+
+/// Pad `raw` with PKCS#7 and encrypt it with AES, the AES variant is chosen by the length of `key`
+pub fn encrypt_padded(
+    key: impl AsRef<[u8]>,
+    raw: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CipherError> {
+    let encryptor = Aes::new_checked(key.as_ref())?;
+    encrypt_padded_with(&encryptor, raw)
+}
+
+/// Pad `raw` with PKCS#7 and encrypt it with any block cipher
+pub fn encrypt_padded_with<C: BlockEncrypt>(
+    encryptor: &C,
+    raw: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CipherError> {
+    encrypt_with(encryptor, pkcs::pad(raw, C::block_size() as u8))
+}
+
+/// Decrypt `encrypted` with AES and remove the PKCS#7 padding, the AES variant is chosen by the
+/// length of `key`
+pub fn ecb_decrypt_padded(
+    key: impl AsRef<[u8]>,
+    encrypted: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CipherError> {
+    let decryptor = Aes::new_checked(key.as_ref())?;
+    ecb_decrypt_padded_with(&decryptor, encrypted)
+}
+
+/// Decrypt `encrypted` with any block cipher and remove the PKCS#7 padding
+pub fn ecb_decrypt_padded_with<C: BlockDecrypt>(
+    decryptor: &C,
+    encrypted: impl AsRef<[u8]>,
+) -> Result<Vec<u8>, CipherError> {
+    let decrypted = ecb_decrypt_with(decryptor, encrypted)?;
+    Ok(pkcs::unpad(decrypted, C::block_size() as u8)?)
+}
+
+/// Pad `data` with PKCS#7 and encrypt it in place with AES, the AES variant is chosen by the
+/// length of `key`. No allocation happens if `data` has a block of spare capacity.
+pub fn encrypt_padded_in_place(
+    key: impl AsRef<[u8]>,
+    data: &mut Vec<u8>,
+) -> Result<(), CipherError> {
+    let encryptor = Aes::new_checked(key.as_ref())?;
+    encrypt_padded_in_place_with(&encryptor, data)
+}
+
+/// Pad `data` with PKCS#7 and encrypt it in place with any block cipher
+pub fn encrypt_padded_in_place_with<C: BlockEncrypt>(
+    encryptor: &C,
+    data: &mut Vec<u8>,
+) -> Result<(), CipherError> {
+    pkcs::pad_in_place(data, C::block_size() as u8);
+    encrypt_in_place_with(encryptor, data)
+}
+
+/// Decrypt `data` in place with AES and remove the PKCS#7 padding, the AES variant is chosen by
+/// the length of `key`. `data` is left decrypted when the padding is invalid.
+pub fn ecb_decrypt_padded_in_place(
+    key: impl AsRef<[u8]>,
+    data: &mut Vec<u8>,
+) -> Result<(), CipherError> {
+    let decryptor = Aes::new_checked(key.as_ref())?;
+    ecb_decrypt_padded_in_place_with(&decryptor, data)
+}
+
+/// Decrypt `data` in place with any block cipher and remove the PKCS#7 padding
+pub fn ecb_decrypt_padded_in_place_with<C: BlockDecrypt>(
+    decryptor: &C,
+    data: &mut Vec<u8>,
+) -> Result<(), CipherError> {
+    ecb_decrypt_in_place_with(decryptor, data)?;
+    Ok(pkcs::unpad_in_place(data, C::block_size() as u8)?)
+}
+
+/// How each line given to an [`EcbDetector`] is encoded
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum LineFormat {
+    Hex,
+    #[default]
+    Base64,
+    Raw,
+}
+
+/// Errors decoding a line given to an [`EcbDetector`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LineError {
+    /// A hex or base64 line which is not valid UTF-8
+    InvalidUtf8,
+    Hex(HexError),
+    Base64(Base64Error),
+}
+
+impl Display for LineError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LineError::InvalidUtf8 => write!(f, "line is not valid UTF-8"),
+            LineError::Hex(error) => write!(f, "{error}"),
+            LineError::Base64(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl Error for LineError {}
+
+/// How much one line looks like ECB output
+#[derive(Clone, Debug, PartialEq)]
+pub struct EcbScore {
+    /// The index of the line
+    pub line: usize,
+    /// The number of blocks which are a repeat of an earlier block in the line
+    pub repeated_blocks: usize,
+    /// The number of whole blocks in the line
+    pub blocks: usize,
+    /// `repeated_blocks` as a fraction of `blocks`, 0 for a line without a whole block
+    pub ratio: f32,
+}
+
+/// The result of scanning lines for ECB encryption
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EcbDetection {
+    /// Every line which could be decoded, most repeated blocks first
+    pub ranked: Vec<EcbScore>,
+    /// The index and error of every line which could not be decoded
+    pub errors: Vec<(usize, LineError)>,
+}
+
+impl EcbDetection {
+    /// The most likely ECB line, if any line has a repeated block
+    pub fn best(&self) -> Option<&EcbScore> {
+        self.ranked
+            .first()
+            .filter(|score| score.repeated_blocks > 0)
+    }
+}
+
+/// Scores lines of ciphertext by how many of their blocks repeat.
+///
+/// ECB encrypts equal plaintext blocks to equal ciphertext blocks, so repeated blocks are a sign
+/// of ECB while other modes almost never repeat a block.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EcbDetector {
+    block_size: usize,
+    format: LineFormat,
+}
+
+impl Default for EcbDetector {
+    fn default() -> Self {
+        Self {
+            block_size: Aes::block_size(),
+            format: LineFormat::default(),
+        }
+    }
+}
+
+impl EcbDetector {
+    /// A detector for base64 lines with the AES block size
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The block size, in bytes, to look for repeats of
+    pub fn block_size(mut self, block_size: usize) -> Self {
+        assert!(block_size > 0, "the block size must be at least one byte");
+        self.block_size = block_size;
+        self
+    }
+
+    /// How the lines are encoded, hex and base64 lines have surrounding whitespace trimmed
+    pub fn format(mut self, format: LineFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Score every line, a line which can not be decoded is reported in the errors and does not
+    /// stop the scan
+    pub fn detect(&self, lines: impl IntoIterator<Item = impl AsRef<[u8]>>) -> EcbDetection {
+        let mut detection = EcbDetection::default();
+        for (line, bytes) in lines.into_iter().enumerate() {
+            match self.decode(bytes.as_ref()) {
+                Ok(bytes) => detection.ranked.push(self.score(line, &bytes)),
+                Err(error) => detection.errors.push((line, error)),
+            }
+        }
+        detection.ranked.sort_by(|x, y| {
+            y.repeated_blocks
+                .cmp(&x.repeated_blocks)
+                .then(y.ratio.total_cmp(&x.ratio))
+                .then(x.line.cmp(&y.line))
+        });
+        detection
+    }
+
+    fn decode(&self, line: &[u8]) -> Result<Vec<u8>, LineError> {
+        let text = || {
+            std::str::from_utf8(line)
+                .map(str::trim)
+                .map_err(|_| LineError::InvalidUtf8)
+        };
+        match self.format {
+            LineFormat::Raw => Ok(line.to_vec()),
+            LineFormat::Hex => {
+                let hex = Hex::try_from(text()?).map_err(LineError::Hex)?;
+                Ok(<&[u8]>::from(&hex).to_vec())
+            }
+            LineFormat::Base64 => base64::decode(text()?).map_err(LineError::Base64),
+        }
+    }
+
+    fn score(&self, line: usize, bytes: &[u8]) -> EcbScore {
+        let blocks = bytes.len() / self.block_size;
+        let unique = bytes.chunks_exact(self.block_size).unique().count();
+        let repeated_blocks = blocks - unique;
+        let ratio = match blocks {
+            0 => 0.0,
+            _ => repeated_blocks as f32 / blocks as f32,
+        };
+        EcbScore {
+            line,
+            repeated_blocks,
+            blocks,
+            ratio,
         }
     }
-    None
+}
+
+/// The index of the base64 line most likely to be encrypted with ECB, lines which are not valid
+/// base64 are skipped
+pub fn detect_ecb(lines: impl IntoIterator<Item = impl Borrow<str>>) -> Option<usize> {
+    let lines = lines
+        .into_iter()
+        .map(|line| line.borrow().to_string())
+        .collect::<Vec<_>>();
+    EcbDetector::new()
+        .detect(lines)
+        .best()
+        .map(|score| score.line)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use base64::Engine;
-    use std::fs::File;
+    use crate::block::Toy;
+    use aes::cipher::{Block, KeyInit};
+    use std::fs::{self, File};
     use std::io::{BufRead, BufReader};
+    use yare::parameterized;
+
+    const KEY: &[u8; 16] = b"YELLOW SUBMARINE";
 
     #[test]
     fn yellow_sub() {
-        let file = File::open("tests/assets/7.txt").unwrap();
-        let base_64 = BufReader::new(file)
-            .lines()
-            .fold(String::new(), |mut acc, e| {
-                acc.push_str(&e.unwrap());
-                acc
-            });
-        let bytes = base64::engine::general_purpose::STANDARD
-            .decode(base_64.as_bytes())
-            .unwrap();
-        let decrypted = ecb_decrypt("YELLOW SUBMARINE", &bytes);
+        let base_64 = fs::read_to_string("tests/assets/7.txt").unwrap();
+        let bytes = base64::decode(base_64).unwrap();
+        let decrypted = ecb_decrypt("YELLOW SUBMARINE", &bytes).unwrap();
         let plaintext = String::from_utf8(decrypted).unwrap();
         assert!(plaintext.starts_with("I'm back and I'm ringin' the bell"))
     }
 
     #[test]
     fn re_encrypt() {
-        let file = File::open("tests/assets/7.txt").unwrap();
-        let base_64 = BufReader::new(file)
-            .lines()
-            .fold(String::new(), |mut acc, e| {
-                acc.push_str(&e.unwrap());
-                acc
-            });
-        let bytes = base64::engine::general_purpose::STANDARD
-            .decode(base_64.as_bytes())
-            .unwrap();
-        let decrypted = ecb_decrypt("YELLOW SUBMARINE", &bytes);
+        let base_64 = fs::read_to_string("tests/assets/7.txt").unwrap();
+        let bytes = base64::decode(base_64).unwrap();
+        let decrypted = ecb_decrypt("YELLOW SUBMARINE", &bytes).unwrap();
         // let plaintext = String::from_utf8(decrypted).unwrap();
         // assert!(plaintext.starts_with("I'm back and I'm ringin' the bell"));
-        let encrypted = encrypt("YELLOW SUBMARINE", &decrypted);
+        let encrypted = encrypt("YELLOW SUBMARINE", &decrypted).unwrap();
         assert_eq!(encrypted, bytes);
     }
 
@@ -104,4 +359,230 @@ mod test {
         let lines = BufReader::new(file).lines().map(|x| x.unwrap());
         assert_eq!(detect_ecb(lines), Some(132));
     }
+
+    #[test]
+    fn detector_ranks_every_line() {
+        let lines = fs::read_to_string("tests/assets/8.txt").unwrap();
+        let detection = EcbDetector::new()
+            .format(LineFormat::Hex)
+            .detect(lines.lines());
+        assert_eq!(detection.ranked.len(), 204);
+        assert_eq!(detection.errors, vec![]);
+        assert_eq!(
+            detection.ranked[0],
+            EcbScore {
+                line: 132,
+                repeated_blocks: 3,
+                blocks: 10,
+                ratio: 0.3
+            }
+        );
+        assert_eq!(detection.ranked[1].repeated_blocks, 0);
+        assert_eq!(detection.ranked[1].line, 0);
+    }
+
+    #[test]
+    fn bad_lines_are_reported() {
+        let good = String::from(&base64::Base64::from([7u8; 32].as_slice()));
+        let lines = ["not base64!", &good, "QUJD"];
+        let detection = EcbDetector::new().detect(lines);
+        assert_eq!(detection.best().map(|score| score.line), Some(1));
+        assert_eq!(detection.ranked.len(), 2);
+        assert!(matches!(
+            detection.errors.as_slice(),
+            [(0, LineError::Base64(_))]
+        ));
+        assert_eq!(detect_ecb(lines), Some(1));
+    }
+
+    #[parameterized(
+    raw_8 = { LineFormat::Raw, 8, b"abcdefghabcdefghabcdefgh".as_slice(), 2, 3 },
+    raw_16 = { LineFormat::Raw, 16, b"abcdefghabcdefghabcdefgh".as_slice(), 0, 1 },
+    hex_4 = { LineFormat::Hex, 4, b" 0102030401020304\r\n".as_slice(), 1, 2 },
+    short = { LineFormat::Raw, 16, b"short".as_slice(), 0, 0 },
+    )]
+    fn block_size_and_format(
+        format: LineFormat,
+        block_size: usize,
+        line: &[u8],
+        repeated_blocks: usize,
+        blocks: usize,
+    ) {
+        let detection = EcbDetector::new()
+            .format(format)
+            .block_size(block_size)
+            .detect([line]);
+        let score = &detection.ranked[0];
+        assert_eq!(
+            (score.repeated_blocks, score.blocks),
+            (repeated_blocks, blocks)
+        );
+    }
+
+    #[test]
+    fn no_repeats_is_not_ecb() {
+        let detection = EcbDetector::new()
+            .format(LineFormat::Raw)
+            .detect([b"0123456789abcdef".as_slice()]);
+        assert_eq!(detection.ranked.len(), 1);
+        assert_eq!(detection.best(), None);
+    }
+
+    #[parameterized(
+    aes_128 = {
+        "2b7e151628aed2a6abf7158809cf4f3c",
+        "6bc1bee22e409f96e93d7e117393172a",
+        "3ad77bb40d7a3660a89ecaf32466ef97"
+    },
+    aes_192 = {
+        "8e73b0f7da0e6452c810f32b809079e562f8ead2522c6b7b",
+        "6bc1bee22e409f96e93d7e117393172a",
+        "bd334f1d6e45f25ff712a214571fa5cc"
+    },
+    aes_256 = {
+        "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4",
+        "6bc1bee22e409f96e93d7e117393172a",
+        "f3eed1bdb5d2a03c064b5a7e3db181f8"
+    },
+    )]
+    fn aes_key_sizes(key: &str, plaintext: &str, ciphertext: &str) {
+        let key = Hex::try_from(key).unwrap();
+        let plaintext = Hex::try_from(plaintext).unwrap();
+        let ciphertext = Hex::try_from(ciphertext).unwrap();
+        let encrypted = encrypt(<&[u8]>::from(&key), <&[u8]>::from(&plaintext)).unwrap();
+        assert_eq!(encrypted, <&[u8]>::from(&ciphertext));
+        assert_eq!(
+            ecb_decrypt(<&[u8]>::from(&key), encrypted).unwrap(),
+            <&[u8]>::from(&plaintext)
+        );
+    }
+
+    #[test]
+    fn toy_cipher() {
+        let toy = Toy::new_from_slice(b"toy key!").unwrap();
+        let raw = b"same 8b!same 8b!differs!";
+        let encrypted = encrypt_with(&toy, raw).unwrap();
+        assert_eq!(encrypted[..8], encrypted[8..16]);
+        assert_ne!(encrypted[..8], encrypted[16..]);
+        assert_eq!(ecb_decrypt_with(&toy, encrypted).unwrap(), raw);
+    }
+
+    #[test]
+    fn invalid_key_length() {
+        assert_eq!(
+            encrypt("SHORT KEY", [0; 16]),
+            Err(CipherError::InvalidKeyLength(9))
+        );
+        assert_eq!(
+            ecb_decrypt("SHORT KEY", [0; 16]),
+            Err(CipherError::InvalidKeyLength(9))
+        );
+    }
+
+    #[parameterized(
+    short = { 15 },
+    trailing_partial_block = { 33 },
+    )]
+    fn not_block_aligned(length: usize) {
+        let expected = Err(CipherError::NotBlockAligned {
+            length,
+            block_size: 16,
+        });
+        assert_eq!(encrypt("YELLOW SUBMARINE", vec![0; length]), expected);
+        assert_eq!(ecb_decrypt("YELLOW SUBMARINE", vec![0; length]), expected);
+    }
+
+    #[test]
+    fn padded_round_trip() {
+        let raw = b"not a multiple of the block size";
+        let encrypted = encrypt_padded("YELLOW SUBMARINE", &raw[..30]).unwrap();
+        assert_eq!(encrypted.len(), 32);
+        assert_eq!(
+            ecb_decrypt_padded("YELLOW SUBMARINE", encrypted).unwrap(),
+            &raw[..30]
+        );
+    }
+
+    #[test]
+    fn padded_toy_cipher() {
+        let toy = Toy::new_from_slice(b"toy key!").unwrap();
+        let encrypted = encrypt_padded_with(&toy, b"8 bytes!").unwrap();
+        assert_eq!(encrypted.len(), 16);
+        assert_eq!(
+            ecb_decrypt_padded_with(&toy, encrypted).unwrap(),
+            b"8 bytes!"
+        );
+    }
+
+    #[test]
+    fn decrypt_padded_bad_padding() {
+        let encrypted = encrypt("YELLOW SUBMARINE", b"ends in a zero!\x00").unwrap();
+        assert_eq!(
+            ecb_decrypt_padded("YELLOW SUBMARINE", encrypted),
+            Err(CipherError::Padding(pkcs::PaddingError::Zero))
+        );
+    }
+
+    #[test]
+    fn in_place_matches_copy() {
+        let base_64 = fs::read_to_string("tests/assets/7.txt").unwrap();
+        let bytes = base64::decode(base_64).unwrap();
+        let mut data = bytes.clone();
+        ecb_decrypt_in_place(KEY, &mut data).unwrap();
+        assert_eq!(data, ecb_decrypt(KEY, &bytes).unwrap());
+        encrypt_in_place(KEY, &mut data).unwrap();
+        assert_eq!(data, bytes);
+    }
+
+    #[test]
+    fn padded_in_place_uses_spare_capacity() {
+        let toy = Toy::new_from_slice(b"toy key!").unwrap();
+        let mut data = Vec::with_capacity(16);
+        data.extend_from_slice(b"12 bytes!!!!");
+        let pointer = data.as_ptr();
+        encrypt_padded_in_place_with(&toy, &mut data).unwrap();
+        assert_eq!(data, encrypt_padded_with(&toy, b"12 bytes!!!!").unwrap());
+        assert_eq!(data.as_ptr(), pointer);
+        ecb_decrypt_padded_in_place_with(&toy, &mut data).unwrap();
+        assert_eq!(data, b"12 bytes!!!!");
+    }
+
+    #[test]
+    fn in_place_not_block_aligned() {
+        let expected = Err(CipherError::NotBlockAligned {
+            length: 20,
+            block_size: 16,
+        });
+        assert_eq!(encrypt_in_place(KEY, &mut [0; 20]), expected);
+        assert_eq!(ecb_decrypt_in_place(KEY, &mut [0; 20]), expected);
+        assert_eq!(ecb_decrypt_padded_in_place(KEY, &mut vec![0; 20]), expected);
+    }
+
+    #[parameterized(
+    empty = { 0 },
+    one = { 1 },
+    odd = { 9 },
+    several = { 133 },
+    )]
+    fn multi_block_matches_serial(blocks: usize) {
+        let raw = (0..blocks * 16)
+            .map(|i| (i * 7 % 256) as u8)
+            .collect::<Vec<_>>();
+        let aes = Aes::new_checked(KEY).unwrap();
+        let serial = raw
+            .chunks_exact(16)
+            .flat_map(|block| {
+                let mut block = Block::<Aes>::clone_from_slice(block);
+                aes.encrypt_block(&mut block);
+                block
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(encrypt_with(&aes, &raw).unwrap(), serial);
+        let mut in_place = raw.clone();
+        encrypt_in_place_with(&aes, &mut in_place).unwrap();
+        assert_eq!(in_place, serial);
+        assert_eq!(ecb_decrypt_with(&aes, &serial).unwrap(), raw);
+        ecb_decrypt_in_place_with(&aes, &mut in_place).unwrap();
+        assert_eq!(in_place, raw);
+    }
 }
@@ -0,0 +1,184 @@
+//          Copyright Nick G 2023.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! Breaking CTR encryption with a reused nonce, https://cryptopals.com/sets/3/challenges/19 and
+//! https://cryptopals.com/sets/3/challenges/20
+//!
+//! Every ciphertext is XORed with the same keystream. Taking the `n`th byte of each ciphertext
+//! gives a column encrypted with a single byte of keystream, which is cracked the same way as
+//! single byte XOR.
+
+use crate::hex::Hex;
+use crate::score::Scorer;
+use crate::xor;
+
+/// How to treat ciphertexts of different lengths
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Lengths {
+    /// Only recover the keystream up to the length of the shortest ciphertext
+    #[default]
+    Truncate,
+    /// Recover the keystream up to the length of the longest ciphertext, later columns are made
+    /// from fewer ciphertexts and are less reliable
+    Ragged,
+}
+
+/// The keystream recovered from a set of ciphertexts
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecoveredKeystream {
+    /// The most likely keystream bytes
+    pub keystream: Vec<u8>,
+    /// How far the best key byte scored above the runner up, as a fraction of the spread between
+    /// the best and worst scores, for each keystream byte. Values near 0 mean another key byte was
+    /// almost as likely.
+    pub confidence: Vec<f32>,
+}
+
+impl RecoveredKeystream {
+    /// Decrypt `ciphertext`, truncating to the length of the recovered keystream
+    pub fn decrypt(&self, ciphertext: impl AsRef<[u8]>) -> Vec<u8> {
+        ciphertext
+            .as_ref()
+            .iter()
+            .zip(&self.keystream)
+            .map(|(c, k)| c ^ k)
+            .collect()
+    }
+}
+
+/// Recover the keystream shared by `ciphertexts`.
+///
+/// Each keystream byte is found from a column of bytes taken from different messages, so the
+/// `scorer` should not rely on neighbouring characters, [`ChiSquared`](crate::score::ChiSquared)
+/// works well.
+pub fn break_keystream(
+    ciphertexts: &[impl AsRef<[u8]>],
+    lengths: Lengths,
+    scorer: &(impl Scorer + ?Sized),
+) -> RecoveredKeystream {
+    let ciphertext_lengths = ciphertexts.iter().map(|c| c.as_ref().len());
+    let length = match lengths {
+        Lengths::Truncate => ciphertext_lengths.min(),
+        Lengths::Ragged => ciphertext_lengths.max(),
+    }
+    .unwrap_or(0);
+
+    let mut recovered = RecoveredKeystream::default();
+    for index in 0..length {
+        let column = ciphertexts
+            .iter()
+            .filter_map(|c| c.as_ref().get(index).copied())
+            .collect::<Vec<_>>();
+        let (key, confidence) = crack_column(&Hex::from(column.as_slice()), scorer);
+        recovered.keystream.push(key);
+        recovered.confidence.push(confidence);
+    }
+    recovered
+}
+
+fn crack_column(column: &Hex, scorer: &(impl Scorer + ?Sized)) -> (u8, f32) {
+    let candidates = xor::rank_single_byte_xor(column, scorer, usize::MAX);
+    let best = &candidates[0];
+    let runner_up = candidates[1].score;
+    let worst = candidates
+        .iter()
+        .map(|candidate| candidate.score)
+        .rev()
+        .find(|score| score.is_finite())
+        .unwrap_or(best.score);
+    let confidence = match best.score > worst {
+        true => (best.score - runner_up.max(worst)) / (best.score - worst),
+        false => 0.0,
+    };
+    (best.key, confidence)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ctr;
+    use crate::score::ChiSquared;
+
+    const KEY: &[u8; 16] = b"YELLOW SUBMARINE";
+    const PLAINTEXTS: [&str; 16] = [
+        "I have met them at close of day",
+        "Coming with vivid faces",
+        "From counter or desk among grey",
+        "Eighteenth-century houses.",
+        "I have passed with a nod of the head",
+        "Or polite meaningless words,",
+        "Or have lingered awhile and said",
+        "Polite meaningless words,",
+        "And thought before I had done",
+        "Of a mocking tale or a gibe",
+        "To please a companion",
+        "Around the fire at the club,",
+        "Being certain that they and I",
+        "But lived where motley is worn:",
+        "All changed, changed utterly:",
+        "A terrible beauty is born.",
+    ];
+
+    fn encrypted() -> Vec<Vec<u8>> {
+        PLAINTEXTS
+            .iter()
+            .map(|p| ctr::encrypt(KEY, [0; 8], p).unwrap())
+            .collect()
+    }
+
+    fn correct_bytes(recovered: &RecoveredKeystream) -> usize {
+        let keystream = ctr::encrypt(KEY, [0; 8], vec![0; recovered.keystream.len()]).unwrap();
+        keystream
+            .iter()
+            .zip(&recovered.keystream)
+            .filter(|(a, b)| a == b)
+            .count()
+    }
+
+    #[test]
+    fn truncate_to_shortest() {
+        let recovered = break_keystream(&encrypted(), Lengths::Truncate, &ChiSquared::english());
+        assert_eq!(recovered.keystream.len(), 21);
+        assert_eq!(recovered.confidence.len(), 21);
+        assert!(correct_bytes(&recovered) >= 18);
+    }
+
+    #[test]
+    fn ragged_lengths() {
+        let recovered = break_keystream(&encrypted(), Lengths::Ragged, &ChiSquared::english());
+        assert_eq!(recovered.keystream.len(), 36);
+        assert!(correct_bytes(&recovered) >= 18);
+    }
+
+    #[test]
+    fn decrypt_with_recovered() {
+        let ciphertexts = encrypted();
+        let recovered = break_keystream(&ciphertexts, Lengths::Truncate, &ChiSquared::english());
+        let decrypted = recovered.decrypt(&ciphertexts[0]);
+        let matching = decrypted
+            .iter()
+            .zip(PLAINTEXTS[0].as_bytes())
+            .filter(|(a, b)| a == b)
+            .count();
+        assert_eq!(decrypted.len(), 21);
+        assert!(matching >= 18);
+    }
+
+    #[test]
+    fn confidence_is_bounded() {
+        let recovered = break_keystream(&encrypted(), Lengths::Ragged, &ChiSquared::english());
+        assert!(recovered.confidence.iter().all(|c| (0.0..=1.0).contains(c)));
+    }
+
+    #[test]
+    fn no_ciphertexts() {
+        let recovered = break_keystream(
+            &Vec::<Vec<u8>>::new(),
+            Lengths::Ragged,
+            &ChiSquared::english(),
+        );
+        assert_eq!(recovered, RecoveredKeystream::default());
+    }
+}
@@ -8,7 +8,10 @@
 
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
 use std::ops::BitXor;
+use std::str::FromStr;
 
 static INDEX_TO_CHAR: [char; 16] = [
     '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'b', 'c', 'd', 'e', 'f',
@@ -39,29 +42,123 @@ pub struct Hex {
     bytes: Vec<u8>,
 }
 
-impl From<&String> for Hex {
-    fn from(string: &String) -> Self {
-        Hex::from(string.as_str())
-    }
+/// Errors that can occur when parsing a hex string
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum HexError {
+    /// A character which is not a hex digit, with its byte offset in the input
+    InvalidCharacter { character: char, index: usize },
+    /// The input has an odd number of hex digits, the value is the number of digits
+    OddLength(usize),
 }
-impl From<&str> for Hex {
-    fn from(string: &str) -> Self {
-        let mut bytes = vec![];
-        if string.is_empty() {
-            return Self { bytes };
+
+impl Display for HexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            HexError::InvalidCharacter { character, index } => {
+                write!(f, "invalid hex character {character:?} at index {index}")
+            }
+            HexError::OddLength(length) => {
+                write!(f, "odd number of hex digits ({length})")
+            }
         }
+    }
+}
+
+impl Error for HexError {}
+
+/// Parses hex strings into [`Hex`].
+///
+/// By default only lower case digits are accepted. Upper case digits, a leading `0x` and
+/// separators (whitespace and colons) can each be opted into.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct HexParser {
+    uppercase: bool,
+    prefix: bool,
+    separators: bool,
+}
+
+impl HexParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept the upper case digits `A-F`
+    pub fn allow_uppercase(mut self, allow: bool) -> Self {
+        self.uppercase = allow;
+        self
+    }
+
+    /// Accept, and ignore, a leading `0x` or `0X`
+    pub fn allow_prefix(mut self, allow: bool) -> Self {
+        self.prefix = allow;
+        self
+    }
 
-        // NEED to ensure an even number of characters
-        let upper = string.chars().step_by(2);
-        let lower = string[1..].chars().step_by(2);
+    /// Accept, and ignore, whitespace and colons between digits
+    pub fn allow_separators(mut self, allow: bool) -> Self {
+        self.separators = allow;
+        self
+    }
 
-        for (high, low) in upper.zip(lower) {
-            let high_nyble = CHAR_TO_BYTE.get(&high).unwrap();
-            let low_nyble = CHAR_TO_BYTE.get(&low).unwrap();
-            let value = (high_nyble << 4) | low_nyble;
-            bytes.push(value);
+    pub fn parse(&self, string: &str) -> Result<Hex, HexError> {
+        let (offset, digits) = match string.strip_prefix("0x").or(string.strip_prefix("0X")) {
+            Some(digits) if self.prefix => (2, digits),
+            _ => (0, string),
+        };
+
+        let mut nibbles = vec![];
+        for (index, character) in digits.char_indices() {
+            if self.separators && (character.is_whitespace() || character == ':') {
+                continue;
+            }
+            let lookup = match self.uppercase {
+                true => character.to_ascii_lowercase(),
+                false => character,
+            };
+            match CHAR_TO_BYTE.get(&lookup) {
+                Some(nibble) => nibbles.push(*nibble),
+                None => {
+                    return Err(HexError::InvalidCharacter {
+                        character,
+                        index: offset + index,
+                    })
+                }
+            }
         }
-        Self { bytes }
+
+        if nibbles.len() % 2 != 0 {
+            return Err(HexError::OddLength(nibbles.len()));
+        }
+
+        let bytes = nibbles
+            .chunks_exact(2)
+            .map(|pair| (pair[0] << 4) | pair[1])
+            .collect();
+        Ok(Hex { bytes })
+    }
+}
+
+impl TryFrom<&String> for Hex {
+    type Error = HexError;
+
+    fn try_from(string: &String) -> Result<Self, Self::Error> {
+        Hex::try_from(string.as_str())
+    }
+}
+
+impl TryFrom<&str> for Hex {
+    type Error = HexError;
+
+    fn try_from(string: &str) -> Result<Self, Self::Error> {
+        HexParser::new().parse(string)
+    }
+}
+
+impl FromStr for Hex {
+    type Err = HexError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        Hex::try_from(string)
     }
 }
 
@@ -114,7 +211,7 @@ mod test {
 
     #[test]
     fn hex_from_empty_string() {
-        assert_eq!(Hex::from(""), Hex { bytes: vec![] });
+        assert_eq!(Hex::try_from(""), Ok(Hex { bytes: vec![] }));
     }
 
     #[parameterized(
@@ -124,19 +221,80 @@ mod test {
     )]
     fn hex_from(input: &str, expected: &[u8]) {
         assert_eq!(
-            Hex::from(input),
-            Hex {
+            Hex::try_from(input),
+            Ok(Hex {
                 bytes: Vec::from(expected)
-            }
+            })
+        );
+    }
+
+    #[parameterized(
+    bad_first = { "g1", 'g', 0 },
+    bad_last = { "01z2", 'z', 2 },
+    uppercase = { "0A", 'A', 1 },
+    prefix = { "0x01", 'x', 1 },
+    space = { "01 02", ' ', 2 },
+    )]
+    fn hex_from_invalid_character(input: &str, character: char, index: usize) {
+        assert_eq!(
+            input.parse::<Hex>(),
+            Err(HexError::InvalidCharacter { character, index })
         );
     }
 
+    #[parameterized(
+    one = { "1", 1 },
+    three = { "abc", 3 },
+    )]
+    fn hex_from_odd_length(input: &str, length: usize) {
+        assert_eq!(Hex::try_from(input), Err(HexError::OddLength(length)));
+    }
+
+    #[parameterized(
+    uppercase = { HexParser::new().allow_uppercase(true), "ABcD", &[171, 205] },
+    lower_prefix = { HexParser::new().allow_prefix(true), "0xabcd", &[171, 205] },
+    upper_prefix = { HexParser::new().allow_prefix(true), "0Xabcd", &[171, 205] },
+    colons = { HexParser::new().allow_separators(true), "ab:cd", &[171, 205] },
+    whitespace = { HexParser::new().allow_separators(true), "ab cd\n\t01", &[171, 205, 1] },
+    everything = {
+        HexParser::new().allow_uppercase(true).allow_prefix(true).allow_separators(true),
+        "0xAB:CD ef",
+        &[171, 205, 239]
+    },
+    )]
+    fn hex_parser_options(parser: HexParser, input: &str, expected: &[u8]) {
+        assert_eq!(
+            parser.parse(input),
+            Ok(Hex {
+                bytes: Vec::from(expected)
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_character_index_accounts_for_prefix() {
+        let parser = HexParser::new().allow_prefix(true);
+        assert_eq!(
+            parser.parse("0xabqd"),
+            Err(HexError::InvalidCharacter {
+                character: 'q',
+                index: 4
+            })
+        );
+    }
+
+    #[test]
+    fn separators_do_not_count_towards_length() {
+        let parser = HexParser::new().allow_separators(true);
+        assert_eq!(parser.parse("ab:c"), Err(HexError::OddLength(3)));
+    }
+
     #[test]
     fn xor_hex_strings() {
         let first = "1c0111001f010100061a024b53535009181c";
         let second = "686974207468652062756c6c277320657965";
         let expected = "746865206b696420646f6e277420706c6179";
-        let xor = Hex::from(first) ^ Hex::from(second);
+        let xor = Hex::try_from(first).unwrap() ^ Hex::try_from(second).unwrap();
         assert_eq!(String::from(&xor), expected.to_string())
     }
 }
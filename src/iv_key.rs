@@ -0,0 +1,300 @@
+//          Copyright Nick G 2023.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! Recovering the key from CBC with IV = key, https://cryptopals.com/sets/4/challenges/27
+//!
+//! When the receiver reports the plaintext of messages it rejects, sending `C1 || 0 || C1` gives
+//! back `P1` in the first block and `D(C1) ^ 0 = P1 ^ IV` in the third, so XORing them reveals the
+//! IV. If the IV is the key, that is the key.
+
+use crate::block::CipherError;
+use crate::cbc;
+use rand::Rng;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+const BLOCK_SIZE: usize = 16;
+
+/// How [`IvKeyOracle`] decides a decrypted message is invalid
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Validation {
+    /// Reject any byte with the high bit set
+    #[default]
+    Ascii,
+    /// Reject plaintext which is not UTF-8
+    Utf8,
+}
+
+impl Validation {
+    /// Whether `plaintext` is accepted
+    pub fn check(&self, plaintext: &[u8]) -> bool {
+        match self {
+            Validation::Ascii => plaintext.is_ascii(),
+            Validation::Utf8 => std::str::from_utf8(plaintext).is_ok(),
+        }
+    }
+}
+
+/// Errors reported by [`IvKeyOracle`] when decrypting
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum IvKeyError {
+    Cipher(CipherError),
+    /// The plaintext failed validation, the error leaks the plaintext
+    InvalidPlaintext(Vec<u8>),
+}
+
+impl Display for IvKeyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            IvKeyError::Cipher(error) => write!(f, "{error}"),
+            IvKeyError::InvalidPlaintext(plaintext) => {
+                write!(
+                    f,
+                    "invalid plaintext {:?}",
+                    String::from_utf8_lossy(plaintext)
+                )
+            }
+        }
+    }
+}
+
+impl Error for IvKeyError {}
+
+impl From<CipherError> for IvKeyError {
+    fn from(error: CipherError) -> Self {
+        IvKeyError::Cipher(error)
+    }
+}
+
+/// Errors from recovering the key
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum IvKeyAttackError {
+    /// The encryption oracle returned less than two blocks
+    CiphertextTooShort(usize),
+    /// The oracle accepted the forged message, so the plaintext was not leaked
+    NotLeaked,
+    /// The oracle rejected the forged message without leaking the plaintext
+    Oracle(CipherError),
+    /// The recovered IV does not decrypt the messages as a key, the IV is not the key
+    NotIvKey,
+}
+
+impl Display for IvKeyAttackError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            IvKeyAttackError::CiphertextTooShort(length) => {
+                write!(f, "ciphertext length ({length}) is less than two blocks")
+            }
+            IvKeyAttackError::NotLeaked => write!(f, "the oracle did not leak the plaintext"),
+            IvKeyAttackError::Oracle(error) => write!(f, "{error}"),
+            IvKeyAttackError::NotIvKey => write!(f, "the oracle does not use the key as the IV"),
+        }
+    }
+}
+
+impl Error for IvKeyAttackError {}
+
+/// Encrypts user data with AES-128-CBC using the key as the IV, and reports the plaintext of
+/// decrypted messages which fail validation
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IvKeyOracle {
+    key: [u8; BLOCK_SIZE],
+    prefix: Vec<u8>,
+    suffix: Vec<u8>,
+    validation: Validation,
+}
+
+impl Default for IvKeyOracle {
+    fn default() -> Self {
+        let mut rng = rand::thread_rng();
+        Self::with_key(Rng::gen(&mut rng))
+    }
+}
+
+impl IvKeyOracle {
+    /// An oracle with a random key and no prefix or suffix
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An oracle with a known key
+    pub fn with_key(key: [u8; BLOCK_SIZE]) -> Self {
+        Self {
+            key,
+            prefix: vec![],
+            suffix: vec![],
+            validation: Validation::default(),
+        }
+    }
+
+    /// Bytes placed before the user data
+    pub fn prefix(mut self, prefix: impl Into<Vec<u8>>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Bytes placed after the user data
+    pub fn suffix(mut self, suffix: impl Into<Vec<u8>>) -> Self {
+        self.suffix = suffix.into();
+        self
+    }
+
+    /// How decrypted messages are validated
+    pub fn validation(mut self, validation: Validation) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    /// Encrypt `prefix || userdata || suffix`
+    pub fn encrypt(&self, userdata: impl AsRef<[u8]>) -> Vec<u8> {
+        let plaintext = [&self.prefix, userdata.as_ref(), &self.suffix].concat();
+        cbc::encrypt_padded(self.key, self.key, plaintext).expect("the key is a valid length")
+    }
+
+    /// Decrypt `ciphertext`, returning the plaintext in the error if it fails validation
+    pub fn decrypt(&self, ciphertext: impl AsRef<[u8]>) -> Result<(), IvKeyError> {
+        let plaintext = cbc::cbc_decrypt_padded(self.key, self.key, ciphertext)?;
+        match self.validation.check(&plaintext) {
+            true => Ok(()),
+            false => Err(IvKeyError::InvalidPlaintext(plaintext)),
+        }
+    }
+}
+
+/// Recover the key from an `encrypt` and `decrypt` oracle pair which use the key as the IV.
+///
+/// The recovered key is checked by decrypting a message with it, so an oracle with a fixed IV
+/// which is not the key gives [`IvKeyAttackError::NotIvKey`].
+pub fn recover_key<E, D>(encrypt: E, decrypt: D) -> Result<[u8; BLOCK_SIZE], IvKeyAttackError>
+where
+    E: Fn(&[u8]) -> Vec<u8>,
+    D: Fn(&[u8]) -> Result<(), IvKeyError>,
+{
+    let chosen = [b'A'; BLOCK_SIZE * 3];
+    let ciphertext = encrypt(&chosen);
+    if ciphertext.len() < BLOCK_SIZE * 2 {
+        return Err(IvKeyAttackError::CiphertextTooShort(ciphertext.len()));
+    }
+
+    // Keeping the original blocks after `C1 || 0 || C1` leaves the padding intact, the block
+    // after the second `C1` is `C2` which decrypts as it did originally
+    let first = &ciphertext[..BLOCK_SIZE];
+    let forged = [first, &[0; BLOCK_SIZE], first, &ciphertext[BLOCK_SIZE..]].concat();
+    let plaintext = match decrypt(&forged) {
+        Ok(()) => return Err(IvKeyAttackError::NotLeaked),
+        Err(IvKeyError::Cipher(error)) => return Err(IvKeyAttackError::Oracle(error)),
+        Err(IvKeyError::InvalidPlaintext(plaintext)) => plaintext,
+    };
+    if plaintext.len() < BLOCK_SIZE * 3 {
+        return Err(IvKeyAttackError::NotLeaked);
+    }
+
+    let mut key = [0; BLOCK_SIZE];
+    for (k, (p1, p3)) in key.iter_mut().zip(
+        plaintext[..BLOCK_SIZE]
+            .iter()
+            .zip(&plaintext[BLOCK_SIZE * 2..]),
+    ) {
+        *k = p1 ^ p3;
+    }
+
+    let decrypted = cbc::cbc_decrypt_padded(key, key, &ciphertext).unwrap_or_default();
+    match decrypted.windows(chosen.len()).any(|w| w == chosen) {
+        true => Ok(key),
+        false => Err(IvKeyAttackError::NotIvKey),
+    }
+}
+
+/// Whether the oracle pair uses the key as the IV, leaking the key
+pub fn uses_key_as_iv<E, D>(encrypt: E, decrypt: D) -> Result<bool, IvKeyAttackError>
+where
+    E: Fn(&[u8]) -> Vec<u8>,
+    D: Fn(&[u8]) -> Result<(), IvKeyError>,
+{
+    match recover_key(encrypt, decrypt) {
+        Ok(_) => Ok(true),
+        Err(IvKeyAttackError::NotIvKey) => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use yare::parameterized;
+
+    const KEY: [u8; 16] = *b"YELLOW SUBMARINE";
+
+    #[parameterized(
+    ascii = { Validation::Ascii },
+    utf8 = { Validation::Utf8 },
+    )]
+    fn recover(validation: Validation) {
+        let oracle = IvKeyOracle::with_key(KEY)
+            .prefix("comment1=cooking%20MCs;userdata=")
+            .suffix(";comment2=%20like%20a%20pound%20of%20bacon")
+            .validation(validation);
+        let key = recover_key(|input| oracle.encrypt(input), |c| oracle.decrypt(c));
+        assert_eq!(key, Ok(KEY));
+    }
+
+    #[test]
+    fn recover_random_key() {
+        let oracle = IvKeyOracle::new();
+        let key = recover_key(|input| oracle.encrypt(input), |c| oracle.decrypt(c)).unwrap();
+        assert_eq!(IvKeyOracle::with_key(key), oracle);
+    }
+
+    #[test]
+    fn valid_messages_are_accepted() {
+        let oracle = IvKeyOracle::with_key(KEY);
+        assert_eq!(oracle.decrypt(oracle.encrypt("plain text")), Ok(()));
+        assert_eq!(
+            oracle.decrypt(oracle.encrypt("caf\u{e9}")),
+            Err(IvKeyError::InvalidPlaintext("caf\u{e9}".into()))
+        );
+    }
+
+    #[test]
+    fn fixed_iv_is_not_key() {
+        let iv = *b"not the key, iv!";
+        let encrypt = |input: &[u8]| cbc::encrypt_padded(KEY, iv, input).unwrap();
+        let decrypt = |ciphertext: &[u8]| {
+            let plaintext = cbc::cbc_decrypt_padded(KEY, iv, ciphertext)?;
+            match Validation::Ascii.check(&plaintext) {
+                true => Ok(()),
+                false => Err(IvKeyError::InvalidPlaintext(plaintext)),
+            }
+        };
+        assert_eq!(uses_key_as_iv(encrypt, decrypt), Ok(false));
+        let oracle = IvKeyOracle::with_key(KEY);
+        assert_eq!(
+            uses_key_as_iv(|input| oracle.encrypt(input), |c| oracle.decrypt(c)),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn oracle_without_leak() {
+        let oracle = IvKeyOracle::with_key(KEY);
+        let result = recover_key(
+            |input| oracle.encrypt(input),
+            |c| match oracle.decrypt(c) {
+                Err(IvKeyError::InvalidPlaintext(_)) => {
+                    Err(IvKeyError::Cipher(CipherError::NotBlockAligned {
+                        length: 0,
+                        block_size: 16,
+                    }))
+                }
+                other => other,
+            },
+        );
+        assert!(matches!(result, Err(IvKeyAttackError::Oracle(_))));
+        let result = recover_key(|input| oracle.encrypt(input), |_| Ok(()));
+        assert_eq!(result, Err(IvKeyAttackError::NotLeaked));
+        let result = recover_key(|_| vec![0; 16], |c| oracle.decrypt(c));
+        assert_eq!(result, Err(IvKeyAttackError::CiphertextTooShort(16)));
+    }
+}
@@ -6,10 +6,28 @@
 
 extern crate core;
 
-mod cbc;
-mod ecb;
-mod hex;
-mod pkcs;
+pub mod base64;
+pub mod bitflip;
+pub mod block;
+pub mod byte_at_a_time;
+pub mod cbc;
+pub mod cbc_mac;
+pub mod crib;
+pub mod ctr;
+pub mod ecb;
+pub mod fixed_nonce;
+pub mod hex;
+pub mod iv_key;
+pub mod mode_detect;
+pub mod padding_oracle;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod pkcs;
+pub mod profile;
+pub mod score;
+pub mod sha1;
+pub mod stream;
+pub mod xor;
 use rand::Rng;
 
 const BLOCK_SIZE: usize = 16;
@@ -27,36 +45,30 @@ fn random_prefix_and_suffix(input: impl AsRef<[u8]>) -> Vec<u8> {
     output
 }
 
-#[derive(Debug, PartialEq, Eq)]
-enum EncryptionMode {
-    ECB,
-    CBC,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncryptionMode {
+    Ecb,
+    Cbc,
 }
 
-fn opaque_cbc_or_ecb_encryptor(input: impl AsRef<[u8]>) -> (Vec<u8>, EncryptionMode) {
+pub fn opaque_cbc_or_ecb_encryptor(input: impl AsRef<[u8]>) -> (Vec<u8>, EncryptionMode) {
     let mut rng = rand::thread_rng();
     let key = Rng::gen::<[u8; BLOCK_SIZE]>(&mut rng);
     let input = pkcs::pad(random_prefix_and_suffix(input), BLOCK_SIZE as u8);
     if Rng::gen_bool(&mut rng, 0.5) {
-        (ecb::encrypt(key.as_ref(), input), EncryptionMode::ECB)
+        (ecb::encrypt(key, input).unwrap(), EncryptionMode::Ecb)
     } else {
         let iv = rand::Rng::gen::<[u8; BLOCK_SIZE]>(&mut rng);
-        (
-            cbc::encrypt(key.as_ref(), iv.as_ref(), input),
-            EncryptionMode::CBC,
-        )
+        (cbc::encrypt(key, iv, input).unwrap(), EncryptionMode::Cbc)
     }
 }
 
-fn detect_cbc_or_ecb(input: impl AsRef<[u8]>) -> EncryptionMode {
-    let input = input.as_ref();
-    let blocks = input.chunks_exact(BLOCK_SIZE).into_iter().take(4);
-    let unique_blocks = blocks.collect::<std::collections::HashSet<_>>();
-    if unique_blocks.len() <= 2 {
-        EncryptionMode::ECB
-    } else {
-        EncryptionMode::CBC
-    }
+/// Guess the mode of a single ciphertext from its repeated blocks, see
+/// [`mode_detect::ModeDetector`] for a confidence or to use an oracle
+pub fn detect_cbc_or_ecb(input: impl AsRef<[u8]>) -> EncryptionMode {
+    mode_detect::ModeDetector::new()
+        .detect_corpus([input])
+        .map_or(EncryptionMode::Cbc, |detection| detection.mode)
 }
 
 #[cfg(test)]
@@ -69,4 +81,12 @@ mod test {
         let (output, mode) = opaque_cbc_or_ecb_encryptor(input);
         assert_eq!(detect_cbc_or_ecb(output), mode);
     }
+
+    #[test]
+    fn detect_with_short_input() {
+        for length in [43, 48, 64] {
+            let (output, mode) = opaque_cbc_or_ecb_encryptor(vec![b'A'; length]);
+            assert_eq!(detect_cbc_or_ecb(output), mode);
+        }
+    }
 }
@@ -0,0 +1,550 @@
+//          Copyright Nick G 2023.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! Command line access to the encodings, ciphers and attacks.
+//!
+//! Input is read from a file, or stdin when no file is given, as hex, base64 or raw bytes. Output
+//! which is bytes can be written in any of the same formats.
+//!
+//! Built with the `cli` feature, `cargo run --features cli -- --help`.
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use cryptopals::base64::{self, Base64};
+use cryptopals::hex::{Hex, HexParser};
+use cryptopals::score::{ChiSquared, NgramLogLikelihood, PrintablePenalty};
+use cryptopals::{cbc, ecb, xor};
+use std::error::Error;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "cryptopals",
+    about = "Encodings, ciphers and attacks from cryptopals"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Convert the input to hex
+    Hex(Input),
+    /// Convert the input to base64
+    B64(Input),
+    /// XOR the input with a repeating key
+    Xor {
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        key: Key,
+        #[command(flatten)]
+        output: Output,
+    },
+    /// Find the key for single byte or repeating key XOR
+    CrackXor {
+        #[command(flatten)]
+        input: Input,
+        #[arg(long, value_enum, default_value_t = CrackMode::Single)]
+        mode: CrackMode,
+    },
+    /// Encrypt or decrypt with AES in ECB mode
+    Ecb {
+        #[arg(value_enum)]
+        direction: Direction,
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        key: Key,
+        #[command(flatten)]
+        output: Output,
+        /// Do not add, or remove, PKCS#7 padding
+        #[arg(long)]
+        no_padding: bool,
+    },
+    /// Encrypt or decrypt with AES in CBC mode
+    Cbc {
+        #[arg(value_enum)]
+        direction: Direction,
+        #[command(flatten)]
+        input: Input,
+        #[command(flatten)]
+        key: Key,
+        /// The IV, in the key format, defaults to all zeros
+        #[arg(long)]
+        iv: Option<String>,
+        #[command(flatten)]
+        output: Output,
+        /// Do not add, or remove, PKCS#7 padding
+        #[arg(long)]
+        no_padding: bool,
+    },
+    /// Find the line of ciphertexts which was encrypted with ECB
+    DetectEcb {
+        /// The file to read, stdin when not given
+        file: Option<PathBuf>,
+        /// The format of each line
+        #[arg(long = "from", value_enum, default_value_t = Format::Hex)]
+        format: Format,
+        /// The block size, in bytes, to look for repeats of
+        #[arg(long, default_value_t = 16, value_parser = clap::value_parser!(u16).range(1..))]
+        block_size: u16,
+        /// Show the score of every line, best first, rather than only the best line
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum Format {
+    Hex,
+    Base64,
+    Raw,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum CrackMode {
+    /// The whole input is encrypted with one key byte
+    Single,
+    /// Every line is a candidate encrypted with one key byte, find the most English line
+    Lines,
+    /// The input is encrypted with a repeating key of unknown length
+    Repeating,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum Direction {
+    Encrypt,
+    Decrypt,
+}
+
+#[derive(Debug, Args)]
+struct Input {
+    /// The file to read, stdin when not given
+    file: Option<PathBuf>,
+    /// The format of the input
+    #[arg(id = "from", long = "from", value_enum, default_value_t = Format::Raw)]
+    format: Format,
+}
+
+#[derive(Debug, Args)]
+struct Key {
+    /// The key
+    #[arg(long)]
+    key: String,
+    /// The format of the key, and IV
+    #[arg(long, value_enum, default_value_t = Format::Raw)]
+    key_format: Format,
+}
+
+#[derive(Debug, Args)]
+struct Output {
+    /// The format of the output
+    #[arg(id = "to", long = "to", value_enum, default_value_t = Format::Hex)]
+    format: Format,
+}
+
+impl Format {
+    fn decode(&self, input: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        match self {
+            Format::Raw => Ok(input.to_vec()),
+            Format::Hex => {
+                let hex = HexParser::new()
+                    .allow_uppercase(true)
+                    .allow_prefix(true)
+                    .allow_separators(true)
+                    .parse(std::str::from_utf8(input)?.trim())?;
+                Ok(<&[u8]>::from(&hex).to_vec())
+            }
+            Format::Base64 => Ok(base64::decode(std::str::from_utf8(input)?.trim())?),
+        }
+    }
+
+    fn encode(&self, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            Format::Raw => bytes.to_vec(),
+            Format::Hex => format!("{}\n", String::from(&Hex::from(bytes))).into_bytes(),
+            Format::Base64 => format!("{}\n", String::from(&Base64::from(bytes))).into_bytes(),
+        }
+    }
+}
+
+impl From<Format> for ecb::LineFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Hex => ecb::LineFormat::Hex,
+            Format::Base64 => ecb::LineFormat::Base64,
+            Format::Raw => ecb::LineFormat::Raw,
+        }
+    }
+}
+
+impl Input {
+    fn read(&self, stdin: impl Read) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.format.decode(&read_file_or(&self.file, stdin)?)
+    }
+}
+
+impl Key {
+    fn bytes(&self, value: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.key_format.decode(value.as_bytes())
+    }
+}
+
+fn read_file_or(file: &Option<PathBuf>, mut stdin: impl Read) -> io::Result<Vec<u8>> {
+    match file {
+        Some(path) => fs::read(path),
+        None => {
+            let mut input = vec![];
+            stdin.read_to_end(&mut input)?;
+            Ok(input)
+        }
+    }
+}
+
+fn english() -> (NgramLogLikelihood, PrintablePenalty) {
+    (
+        NgramLogLikelihood::english_bigrams(),
+        PrintablePenalty::default(),
+    )
+}
+
+fn run(cli: Cli, stdin: impl Read, mut stdout: impl Write) -> Result<(), Box<dyn Error>> {
+    match cli.command {
+        Command::Hex(input) => stdout.write_all(&Format::Hex.encode(&input.read(stdin)?))?,
+        Command::B64(input) => stdout.write_all(&Format::Base64.encode(&input.read(stdin)?))?,
+        Command::Xor { input, key, output } => {
+            let key = key.bytes(&key.key)?;
+            if key.is_empty() {
+                return Err("the key must not be empty".into());
+            }
+            let encrypted = xor::xor_encrypt(&input.read(stdin)?, &key);
+            stdout.write_all(&output.format.encode((&encrypted).into()))?;
+        }
+        Command::CrackXor { input, mode } => crack_xor(&input, mode, stdin, stdout)?,
+        Command::Ecb {
+            direction,
+            input,
+            key,
+            output,
+            no_padding,
+        } => {
+            let data = input.read(stdin)?;
+            let key = key.bytes(&key.key)?;
+            let result = match (direction, no_padding) {
+                (Direction::Encrypt, false) => ecb::encrypt_padded(key, data),
+                (Direction::Encrypt, true) => ecb::encrypt(key, data),
+                (Direction::Decrypt, false) => ecb::ecb_decrypt_padded(key, data),
+                (Direction::Decrypt, true) => ecb::ecb_decrypt(key, data),
+            }?;
+            stdout.write_all(&output.format.encode(&result))?;
+        }
+        Command::Cbc {
+            direction,
+            input,
+            key,
+            iv,
+            output,
+            no_padding,
+        } => {
+            let data = input.read(stdin)?;
+            let iv = match iv {
+                Some(iv) => key.bytes(&iv)?,
+                None => vec![0; 16],
+            };
+            let key = key.bytes(&key.key)?;
+            let result = match (direction, no_padding) {
+                (Direction::Encrypt, false) => cbc::encrypt_padded(key, iv, data),
+                (Direction::Encrypt, true) => cbc::encrypt(key, iv, data),
+                (Direction::Decrypt, false) => cbc::cbc_decrypt_padded(key, iv, data),
+                (Direction::Decrypt, true) => cbc::cbc_decrypt(key, iv, data),
+            }?;
+            stdout.write_all(&output.format.encode(&result))?;
+        }
+        Command::DetectEcb {
+            file,
+            format,
+            block_size,
+            all,
+        } => {
+            let input = read_file_or(&file, stdin)?;
+            // Blank lines are skipped, so keep each line's number in the input for reporting
+            let (numbers, lines): (Vec<_>, Vec<_>) = input
+                .split(|b| *b == b'\n')
+                .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+                .enumerate()
+                .filter(|(_, line)| !line.is_empty())
+                .unzip();
+            let detection = ecb::EcbDetector::new()
+                .format(format.into())
+                .block_size(block_size.into())
+                .detect(lines);
+            if all {
+                for score in &detection.ranked {
+                    writeln!(
+                        stdout,
+                        "{}\t{}/{}",
+                        numbers[score.line], score.repeated_blocks, score.blocks
+                    )?;
+                }
+                for (line, error) in &detection.errors {
+                    writeln!(stdout, "{}\terror: {error}", numbers[*line])?;
+                }
+            } else {
+                match detection.best() {
+                    Some(score) => writeln!(stdout, "{}", numbers[score.line])?,
+                    None => return Err("no line was encrypted with ECB".into()),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn crack_xor(
+    input: &Input,
+    mode: CrackMode,
+    stdin: impl Read,
+    mut stdout: impl Write,
+) -> Result<(), Box<dyn Error>> {
+    match mode {
+        CrackMode::Single => {
+            let cipher = input.read(stdin)?;
+            let (key, plaintext) = xor::crack_single_byte_xor(&Hex::from(&cipher[..]), &english());
+            writeln!(stdout, "key: {key:02x}\n{plaintext}")?;
+        }
+        CrackMode::Lines => {
+            let raw = read_file_or(&input.file, stdin)?;
+            let codes = raw
+                .split(|b| *b == b'\n')
+                .filter(|line| !line.is_empty())
+                .map(|line| Ok(Hex::from(&input.format.decode(line)?[..])))
+                .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+            if codes.is_empty() {
+                return Err("no lines to crack".into());
+            }
+            let (key, plaintext, line, _) = xor::crack_list_of_codes(&codes, &english());
+            writeln!(stdout, "line: {line}\nkey: {key:02x}\n{plaintext}")?;
+        }
+        CrackMode::Repeating => {
+            let cipher = Hex::from(&input.read(stdin)?[..]);
+            let cracked = xor::crack_repeating_xor_with(
+                &cipher,
+                &ChiSquared::english(),
+                &xor::KeySizeEstimator::new(),
+                xor::KEY_SIZE_ATTEMPTS,
+            )
+            .ok_or("the input is too short to find a key size")?;
+            writeln!(
+                stdout,
+                "key: {}\n{}",
+                String::from(&Hex::from(&cracked.key[..])),
+                String::from_utf8_lossy(&cracked.plaintext)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli, io::stdin().lock(), io::stdout().lock()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use yare::parameterized;
+
+    fn run_with(args: &[&str], stdin: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let cli = Cli::try_parse_from([&["cryptopals"], args].concat())?;
+        let mut stdout = vec![];
+        run(cli, stdin, &mut stdout)?;
+        Ok(stdout)
+    }
+
+    fn run_text(args: &[&str], stdin: &[u8]) -> String {
+        String::from_utf8(run_with(args, stdin).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn cli_is_valid() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn hex_to_base64() {
+        let hex = b"49276d206b696c6c696e6720796f757220627261696e206c696b65206120706f69736f6e6f7573206d757368726f6f6d\n";
+        assert_eq!(
+            run_text(&["b64", "--from", "hex"], hex),
+            "SSdtIGtpbGxpbmcgeW91ciBicmFpbiBsaWtlIGEgcG9pc29ub3VzIG11c2hyb29t\n"
+        );
+    }
+
+    #[parameterized(
+    raw = { "raw", b"hi".as_slice() },
+    hex = { "hex", b"0x68:69".as_slice() },
+    base64 = { "base64", b"aGk=\n".as_slice() },
+    )]
+    fn to_hex(format: &str, input: &[u8]) {
+        assert_eq!(run_text(&["hex", "--from", format], input), "6869\n");
+    }
+
+    #[test]
+    fn xor_with_key() {
+        let input = b"Burning 'em, if you ain't quick and nimble\nI go crazy when I hear a cymbal";
+        assert_eq!(
+            run_text(&["xor", "--key", "ICE"], input),
+            "0b3637272a2b2e63622c2e69692a23693a2a3c6324202d623d63343c2a26226324272765272a282b2f20430a652e2c652a3124333a653e2b2027630c692b20283165286326302e27282f\n"
+        );
+    }
+
+    #[test]
+    fn crack_single_byte() {
+        let cipher = b"1b37373331363f78151b7f2b783431333d78397828372d363c78373e783a393b3736";
+        assert_eq!(
+            run_text(&["crack-xor", "--from", "hex"], cipher),
+            "key: 58\nCooking MC's like a pound of bacon\n"
+        );
+    }
+
+    #[test]
+    fn crack_lines() {
+        let output = run_text(
+            &[
+                "crack-xor",
+                "--mode",
+                "lines",
+                "--from",
+                "hex",
+                "tests/assets/4.txt",
+            ],
+            b"",
+        );
+        assert_eq!(
+            output,
+            "line: 170\nkey: 35\nNow that the party is jumping\n\n"
+        );
+    }
+
+    #[test]
+    fn crack_repeating() {
+        let output = run_text(
+            &[
+                "crack-xor",
+                "--mode",
+                "repeating",
+                "--from",
+                "base64",
+                "tests/assets/6.txt",
+            ],
+            b"",
+        );
+        assert!(output.starts_with(
+            "key: 5465726d696e61746f7220583a204272696e6720746865206e6f697365\nI'm back and I'm ringin' the bell"
+        ));
+    }
+
+    #[test]
+    fn ecb_decrypt_file() {
+        let output = run_text(
+            &[
+                "ecb",
+                "decrypt",
+                "--key",
+                "YELLOW SUBMARINE",
+                "--from",
+                "base64",
+                "--to",
+                "raw",
+                "tests/assets/7.txt",
+            ],
+            b"",
+        );
+        assert!(output.starts_with("I'm back and I'm ringin' the bell"));
+    }
+
+    #[parameterized(
+    padded = { &[] },
+    unpadded = { &["--no-padding"] },
+    )]
+    fn cbc_round_trip(extra: &[&str]) {
+        let key = [
+            "--key",
+            "000102030405060708090a0b0c0d0e0f",
+            "--key-format",
+            "hex",
+        ];
+        let iv = ["--iv", "0f0e0d0c0b0a09080706050403020100"];
+        let plaintext = b"sixteen byte msg";
+        let encrypt = [&["cbc", "encrypt"], &key[..], &iv, extra].concat();
+        let encrypted = run_with(&encrypt, plaintext).unwrap();
+        let decrypt = [
+            &["cbc", "decrypt", "--from", "hex", "--to", "raw"],
+            &key[..],
+            &iv,
+            extra,
+        ]
+        .concat();
+        assert_eq!(run_with(&decrypt, &encrypted).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn detect_ecb() {
+        assert_eq!(
+            run_text(&["detect-ecb", "tests/assets/8.txt"], b""),
+            "132\n"
+        );
+        let all = run_text(&["detect-ecb", "--all", "tests/assets/8.txt"], b"");
+        assert!(all.starts_with("132\t3/10\n0\t0/10\n"));
+        let bad = run_text(
+            &[
+                "detect-ecb",
+                "--from",
+                "base64",
+                "--all",
+                "--block-size",
+                "3",
+            ],
+            b"QUJDQUJD\n!\n",
+        );
+        assert_eq!(
+            bad,
+            "0\t1/2\n1\terror: invalid base64 character '!' at index 0\n"
+        );
+    }
+
+    #[test]
+    fn detect_ecb_keeps_line_numbers() {
+        let input = b"0123456789abcdef\r\n\nABCDABCD\r\n\n!\n";
+        let args = ["detect-ecb", "--from", "raw", "--block-size", "4", "--all"];
+        assert_eq!(run_text(&args, input), "2\t1/2\n0\t0/4\n4\t0/0\n");
+        let args = ["detect-ecb", "--from", "hex", "--all"];
+        assert_eq!(
+            run_text(&args, b"00\n\nzz\r\n"),
+            "0\t0/0\n2\terror: invalid hex character 'z' at index 0\n"
+        );
+        let args = ["detect-ecb", "--from", "raw", "--block-size", "4"];
+        assert_eq!(run_text(&args, input), "2\n");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(run_with(&["hex", "--from", "hex"], b"abc").is_err());
+        assert!(run_with(&["ecb", "encrypt", "--key", "short"], b"abc").is_err());
+        assert!(run_with(&["xor", "--key", ""], b"abc").is_err());
+        assert!(run_with(&["hex", "missing/file"], b"").is_err());
+    }
+}
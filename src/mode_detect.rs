@@ -0,0 +1,415 @@
+//          Copyright Nick G 2023.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! Telling ECB from CBC, https://cryptopals.com/sets/2/challenges/11
+//!
+//! ECB encrypts equal plaintext blocks to equal ciphertext blocks, while CBC output looks random.
+//! The number of repeated ciphertext blocks is compared against how many repeats random blocks
+//! would have by chance, a birthday bound, giving a confidence rather than a guess.
+//!
+//! With an oracle the detector picks its own plaintext, long enough that whole blocks of it repeat
+//! no matter how many unknown bytes the oracle puts in front. Without an oracle only the repeats
+//! already in the ciphertexts can be used.
+
+use crate::block::Aes;
+use crate::EncryptionMode;
+use aes::cipher::BlockSizeUser;
+use itertools::Itertools;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// The largest block size that will be searched for
+const MAX_BLOCK_SIZE: usize = 64;
+
+/// The most repeated blocks the chosen plaintext will ask for
+const MAX_REPEATS: usize = 1024;
+
+/// The filler byte used for the chosen plaintext
+const FILLER: u8 = b'A';
+
+/// The default chance of calling random ciphertext ECB
+pub const DEFAULT_SIGNIFICANCE: f64 = 1e-6;
+
+/// Errors from detecting the block cipher mode
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ModeDetectError {
+    /// The ciphertext length never changed with the input length
+    BlockSizeNotFound,
+    /// The ciphertext length follows the input length byte for byte, like a stream cipher
+    NotBlockCipher,
+    /// There was not a whole block of ciphertext to look at
+    NoBlocks,
+}
+
+impl Display for ModeDetectError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ModeDetectError::BlockSizeNotFound => write!(f, "unable to determine block size"),
+            ModeDetectError::NotBlockCipher => write!(f, "the oracle is not a block cipher"),
+            ModeDetectError::NoBlocks => write!(f, "no whole ciphertext blocks"),
+        }
+    }
+}
+
+impl Error for ModeDetectError {}
+
+/// The detected mode and the evidence for it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Detection {
+    /// The most likely mode
+    pub mode: EncryptionMode,
+    /// The probability, from 0.5 to 1, that `mode` is correct, with both modes equally likely
+    /// beforehand. For [`EncryptionMode::Ecb`] it is `1 - p_value`. For
+    /// [`EncryptionMode::Cbc`] it depends on the chance that ECB would have been detected: with
+    /// an oracle fewer repeats than were planted rules ECB out, giving 1, while without one ECB of
+    /// plaintext with no repeated blocks looks the same as CBC, giving 0.5.
+    pub confidence: f64,
+    /// The chance of random blocks repeating at least `repeated_blocks` times, the mode is
+    /// [`EncryptionMode::Ecb`] when this is below the significance
+    pub p_value: f64,
+    /// The block size used
+    pub block_size: usize,
+    /// The number of whole ciphertext blocks examined
+    pub blocks: usize,
+    /// The number of blocks which repeat an earlier block of the same ciphertext
+    pub repeated_blocks: usize,
+    /// The number of repeats expected by chance if the blocks were random
+    pub expected_repeats: f64,
+}
+
+/// Detects whether ECB or CBC was used
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModeDetector {
+    block_size: Option<usize>,
+    significance: f64,
+}
+
+impl Default for ModeDetector {
+    fn default() -> Self {
+        Self {
+            block_size: None,
+            significance: DEFAULT_SIGNIFICANCE,
+        }
+    }
+}
+
+impl ModeDetector {
+    /// A detector which finds the block size from the oracle, or uses the AES block size for
+    /// ciphertexts
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use a known block size, in bytes, instead of searching for it
+    pub fn block_size(mut self, block_size: usize) -> Self {
+        assert!(block_size > 0, "the block size must be at least one byte");
+        self.block_size = Some(block_size);
+        self
+    }
+
+    /// The largest acceptable chance of the repeats in random ciphertext being called ECB
+    pub fn significance(mut self, significance: f64) -> Self {
+        assert!(
+            significance > 0.0 && significance < 1.0,
+            "the significance must be between 0 and 1"
+        );
+        self.significance = significance;
+        self
+    }
+
+    /// Detect the mode of `oracle`, which encrypts the input it is given, possibly with unknown
+    /// bytes before and after.
+    ///
+    /// The oracle may change its prefix, key or mode between calls, the mode is detected for the
+    /// final call.
+    pub fn detect<O>(&self, mut oracle: O) -> Result<Detection, ModeDetectError>
+    where
+        O: FnMut(&[u8]) -> Vec<u8>,
+    {
+        let (block_size, baseline) = match self.block_size {
+            Some(block_size) => (block_size, oracle(&[]).len()),
+            None => find_block_size(&mut oracle)?,
+        };
+
+        // The chosen plaintext always contains `repeats + 1` whole blocks of filler, however the
+        // prefix lines up, ask for enough that random blocks can't match as often
+        let mut repeats = 1;
+        while repeats < MAX_REPEATS {
+            let blocks = baseline / block_size + repeats + 2;
+            let chance = poisson_tail(repeats, expected_repeats(&[blocks], block_size));
+            if chance < self.significance {
+                break;
+            }
+            repeats *= 2;
+        }
+        let encrypted = oracle(&vec![FILLER; (repeats + 2) * block_size - 1]);
+        let mut detection = self.evaluate(&[encrypted], block_size)?;
+
+        // ECB could not have produced fewer repeats than were planted
+        if detection.mode == EncryptionMode::Cbc && detection.repeated_blocks < repeats {
+            detection.confidence = cbc_confidence(1.0);
+        }
+        Ok(detection)
+    }
+
+    /// Detect the mode of ciphertexts without an oracle. Repeats are only counted within each
+    /// ciphertext, as CBC with a fixed IV repeats blocks between messages which start the same.
+    pub fn detect_corpus(
+        &self,
+        ciphertexts: impl IntoIterator<Item = impl AsRef<[u8]>>,
+    ) -> Result<Detection, ModeDetectError> {
+        let block_size = self.block_size.unwrap_or(Aes::block_size());
+        let ciphertexts = ciphertexts.into_iter().collect::<Vec<_>>();
+        self.evaluate(&ciphertexts, block_size)
+    }
+
+    fn evaluate(
+        &self,
+        ciphertexts: &[impl AsRef<[u8]>],
+        block_size: usize,
+    ) -> Result<Detection, ModeDetectError> {
+        let counts = ciphertexts
+            .iter()
+            .map(|c| c.as_ref().len() / block_size)
+            .collect::<Vec<_>>();
+        let blocks = counts.iter().sum::<usize>();
+        if blocks == 0 {
+            return Err(ModeDetectError::NoBlocks);
+        }
+        let repeated_blocks = ciphertexts
+            .iter()
+            .zip(&counts)
+            .map(|(c, count)| count - c.as_ref().chunks_exact(block_size).unique().count())
+            .sum::<usize>();
+        let expected_repeats = expected_repeats(&counts, block_size);
+
+        let p_value = poisson_tail(repeated_blocks, expected_repeats);
+        let (mode, confidence) = match p_value < self.significance {
+            true => (EncryptionMode::Ecb, 1.0 - p_value),
+            false => (EncryptionMode::Cbc, cbc_confidence(0.0)),
+        };
+        Ok(Detection {
+            mode,
+            confidence,
+            p_value,
+            block_size,
+            blocks,
+            repeated_blocks,
+            expected_repeats,
+        })
+    }
+}
+
+/// The chance that a ciphertext not called ECB is CBC, when ECB is called ECB with chance
+/// `power`. The tiny chance of calling CBC ECB, the significance, is ignored.
+fn cbc_confidence(power: f64) -> f64 {
+    1.0 / (2.0 - power)
+}
+
+/// Returns the block size and the length of the ciphertext for empty input.
+///
+/// Every padded ciphertext is a whole number of blocks, so once the length has changed the
+/// greatest common divisor of the lengths is the block size, even when a random prefix changes
+/// the length between calls.
+fn find_block_size(
+    oracle: &mut impl FnMut(&[u8]) -> Vec<u8>,
+) -> Result<(usize, usize), ModeDetectError> {
+    let baseline = oracle(&[]).len();
+    let mut divisor = baseline;
+    let mut changed = false;
+    for added in 1..=MAX_BLOCK_SIZE {
+        let length = oracle(&vec![FILLER; added]).len();
+        changed |= length != baseline;
+        divisor = gcd(divisor, length);
+    }
+    match (changed, divisor) {
+        (false, _) | (_, 0) => Err(ModeDetectError::BlockSizeNotFound),
+        (true, 1) => Err(ModeDetectError::NotBlockCipher),
+        (true, block_size) => Ok((block_size, baseline)),
+    }
+}
+
+fn gcd(mut a: usize, mut b: usize) -> usize {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// The birthday bound, each pair of random blocks within a ciphertext matches with probability
+/// `256^-block_size`
+fn expected_repeats(blocks: &[usize], block_size: usize) -> f64 {
+    let pairs = blocks
+        .iter()
+        .map(|&n| (n * n.saturating_sub(1)) as f64 / 2.0)
+        .sum::<f64>();
+    pairs / 256f64.powi(block_size.min(i32::MAX as usize) as i32)
+}
+
+/// The probability of at least `count` events when `expected` happen on average
+fn poisson_tail(count: usize, expected: f64) -> f64 {
+    if count == 0 {
+        return 1.0;
+    }
+    if expected <= 0.0 {
+        return 0.0;
+    }
+    let ln_factorial = |k: usize| (2..=k).map(|i| (i as f64).ln()).sum::<f64>();
+    let probability = |k: usize| (k as f64 * expected.ln() - expected - ln_factorial(k)).exp();
+    if count as f64 > expected {
+        // Past the mean the terms only shrink, sum them directly to keep tiny tails accurate
+        let mut term = probability(count);
+        let mut sum = 0.0;
+        let mut k = count;
+        while term > 0.0 && term > sum * f64::EPSILON {
+            sum += term;
+            k += 1;
+            term *= expected / k as f64;
+        }
+        sum.min(1.0)
+    } else {
+        let below = (0..count).map(probability).sum::<f64>();
+        (1.0 - below).max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::Toy;
+    use crate::{cbc, ecb, opaque_cbc_or_ecb_encryptor};
+    use aes::cipher::KeyInit;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use yare::parameterized;
+
+    const KEY: &[u8; 16] = b"YELLOW SUBMARINE";
+
+    fn oracle(mode: EncryptionMode, prefix: usize) -> impl Fn(&[u8]) -> Vec<u8> {
+        move |input| {
+            let plaintext = [&vec![7; prefix], input, b"suffix"].concat();
+            match mode {
+                EncryptionMode::Ecb => ecb::encrypt_padded(KEY, plaintext),
+                EncryptionMode::Cbc => cbc::encrypt_padded(KEY, [0; 16], plaintext),
+            }
+            .unwrap()
+        }
+    }
+
+    #[parameterized(
+    ecb_no_prefix = { EncryptionMode::Ecb, 0 },
+    ecb_prefix_1 = { EncryptionMode::Ecb, 1 },
+    ecb_prefix_15 = { EncryptionMode::Ecb, 15 },
+    ecb_prefix_37 = { EncryptionMode::Ecb, 37 },
+    cbc_no_prefix = { EncryptionMode::Cbc, 0 },
+    cbc_prefix_15 = { EncryptionMode::Cbc, 15 },
+    cbc_prefix_37 = { EncryptionMode::Cbc, 37 },
+    )]
+    fn any_prefix_length(mode: EncryptionMode, prefix: usize) {
+        let detection = ModeDetector::new().detect(oracle(mode, prefix)).unwrap();
+        assert_eq!(detection.mode, mode);
+        assert_eq!(detection.block_size, 16);
+        assert!(detection.confidence > 0.999_999, "{detection:?}");
+        match mode {
+            EncryptionMode::Ecb => assert!(detection.p_value < 1e-6, "{detection:?}"),
+            EncryptionMode::Cbc => assert_eq!(detection.p_value, 1.0, "{detection:?}"),
+        }
+    }
+
+    #[test]
+    fn random_oracle() {
+        for _ in 0..20 {
+            let mut actual = None;
+            let detection = ModeDetector::new()
+                .detect(|input: &[u8]| {
+                    let (encrypted, mode) = opaque_cbc_or_ecb_encryptor(input);
+                    actual = Some(mode);
+                    encrypted
+                })
+                .unwrap();
+            assert_eq!(Some(detection.mode), actual);
+            assert_eq!(
+                detection.p_value < DEFAULT_SIGNIFICANCE,
+                detection.mode == EncryptionMode::Ecb,
+                "{detection:?}"
+            );
+            assert!(detection.confidence > 0.999_999, "{detection:?}");
+        }
+    }
+
+    #[test]
+    fn toy_block_size() {
+        let toy = Toy::new_from_slice(b"toy key!").unwrap();
+        let detection = ModeDetector::new()
+            .detect(|input: &[u8]| {
+                ecb::encrypt_padded_with(&toy, [b"abc", input].concat()).unwrap()
+            })
+            .unwrap();
+        assert_eq!(detection.block_size, 8);
+        assert_eq!(detection.mode, EncryptionMode::Ecb);
+    }
+
+    #[test]
+    fn not_a_block_cipher() {
+        let error = ModeDetector::new().detect(|input: &[u8]| [b"xyz", input].concat());
+        assert_eq!(error, Err(ModeDetectError::NotBlockCipher));
+        let error = ModeDetector::new().detect(|_: &[u8]| vec![0; 32]);
+        assert_eq!(error, Err(ModeDetectError::BlockSizeNotFound));
+    }
+
+    #[test]
+    fn corpus() {
+        let plaintext = [b"YELLOW SUBMARINE".as_slice(); 4].concat();
+        let ecb = ecb::encrypt(KEY, &plaintext).unwrap();
+        let cbc = cbc::encrypt(KEY, [0; 16], &plaintext).unwrap();
+
+        let detection = ModeDetector::new().detect_corpus([&ecb]).unwrap();
+        assert_eq!(detection.mode, EncryptionMode::Ecb);
+        assert_eq!((detection.blocks, detection.repeated_blocks), (4, 3));
+        assert!(detection.p_value < 1e-6);
+        assert_eq!(detection.confidence, 1.0 - detection.p_value);
+
+        let detection = ModeDetector::new().detect_corpus([&cbc, &cbc]).unwrap();
+        assert_eq!(detection.mode, EncryptionMode::Cbc);
+        assert_eq!((detection.blocks, detection.repeated_blocks), (8, 0));
+        assert_eq!(detection.confidence, 0.5);
+    }
+
+    #[test]
+    fn small_blocks_repeat_by_chance() {
+        // 2 byte blocks of random data repeat often, a few repeats is not evidence of ECB
+        let mut rng = StdRng::seed_from_u64(11);
+        let random = (0..2048).map(|_| rng.gen::<u8>()).collect::<Vec<_>>();
+        let detection = ModeDetector::new()
+            .block_size(2)
+            .detect_corpus([&random])
+            .unwrap();
+        assert!(detection.expected_repeats > 1.0);
+        assert_eq!(detection.mode, EncryptionMode::Cbc);
+        assert!(detection.p_value >= DEFAULT_SIGNIFICANCE, "{detection:?}");
+        assert!(detection.confidence >= 0.5, "{detection:?}");
+    }
+
+    #[test]
+    fn no_blocks() {
+        let error = ModeDetector::new().detect_corpus([b"short"]);
+        assert_eq!(error, Err(ModeDetectError::NoBlocks));
+    }
+
+    #[parameterized(
+    none = { 0, 0.5, 1.0 },
+    certain = { 3, 0.0, 0.0 },
+    one = { 1, 1.0, 0.632_120_56 },
+    tiny = { 1, 1e-30, 1e-30 },
+    two_tiny = { 2, 1e-10, 5e-21 },
+    )]
+    fn tail(count: usize, expected: f64, probability: f64) {
+        let tail = poisson_tail(count, expected);
+        assert!(
+            (tail - probability).abs() <= probability * 1e-6,
+            "{tail} != {probability}"
+        );
+    }
+}
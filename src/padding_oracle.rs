@@ -0,0 +1,253 @@
+//          Copyright Nick G 2023.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! CBC padding oracle attack, https://cryptopals.com/sets/3/challenges/17
+//!
+//! Given an oracle which reports whether a ciphertext decrypts to valid PKCS#7 padding, the
+//! plaintext can be recovered one byte at a time. Each ciphertext block is paired with a forged
+//! previous block, the forged block is tweaked until the oracle accepts the padding which reveals
+//! the block cipher output for that byte.
+
+use crate::block::CipherError;
+use crate::pkcs::{self, PaddingError};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// Errors from running the padding oracle attack
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PaddingOracleError {
+    /// The IV and ciphertext do not make up whole blocks
+    InvalidInput(CipherError),
+    /// No byte value produced valid padding, the oracle is likely not a padding oracle
+    NoValidPadding { block: usize, position: usize },
+}
+
+impl Display for PaddingOracleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PaddingOracleError::InvalidInput(error) => write!(f, "{error}"),
+            PaddingOracleError::NoValidPadding { block, position } => write!(
+                f,
+                "no valid padding found for block {block} at position {position}"
+            ),
+        }
+    }
+}
+
+impl Error for PaddingOracleError {}
+
+/// The result of a successful padding oracle attack
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Recovered {
+    /// The recovered plaintext, still including the PKCS#7 padding
+    pub plaintext: Vec<u8>,
+    /// The block size, taken from the IV length
+    pub block_size: usize,
+    /// The number of times the oracle was queried
+    pub queries: usize,
+}
+
+impl Recovered {
+    /// The recovered plaintext with the PKCS#7 padding removed
+    pub fn unpadded(&self) -> Result<Vec<u8>, PaddingError> {
+        pkcs::unpad(&self.plaintext, self.block_size as u8)
+    }
+}
+
+/// Recover the plaintext of `ciphertext` using a padding `oracle`.
+///
+/// The `oracle` is called as `oracle(iv, ciphertext)` and must return `true` when the decryption
+/// has valid PKCS#7 padding. The block size is taken from the length of `iv`.
+pub fn decrypt<O>(
+    oracle: O,
+    iv: impl AsRef<[u8]>,
+    ciphertext: impl AsRef<[u8]>,
+) -> Result<Recovered, PaddingOracleError>
+where
+    O: Fn(&[u8], &[u8]) -> bool,
+{
+    let iv = iv.as_ref();
+    let ciphertext = ciphertext.as_ref();
+    let block_size = iv.len();
+    if block_size == 0 {
+        return Err(PaddingOracleError::InvalidInput(
+            CipherError::InvalidIvLength {
+                expected: 1,
+                actual: 0,
+            },
+        ));
+    }
+    if ciphertext.is_empty() || ciphertext.len() % block_size != 0 {
+        return Err(PaddingOracleError::InvalidInput(
+            CipherError::NotBlockAligned {
+                length: ciphertext.len(),
+                block_size,
+            },
+        ));
+    }
+
+    let mut queries = 0;
+    let mut counted = |forged: &[u8], target: &[u8]| {
+        queries += 1;
+        oracle(forged, target)
+    };
+
+    let mut plaintext = Vec::with_capacity(ciphertext.len());
+    let previous_blocks = std::iter::once(iv).chain(ciphertext.chunks_exact(block_size));
+    for (block, (previous, target)) in previous_blocks
+        .zip(ciphertext.chunks_exact(block_size))
+        .enumerate()
+    {
+        let intermediate = recover_intermediate(&mut counted, previous, target)
+            .map_err(|position| PaddingOracleError::NoValidPadding { block, position })?;
+        plaintext.extend(intermediate.iter().zip(previous).map(|(i, p)| i ^ p));
+    }
+
+    Ok(Recovered {
+        plaintext,
+        block_size,
+        queries,
+    })
+}
+
+/// Find the block cipher decryption of `target`, before it is XORed with `previous`.
+///
+/// On failure the position in the block which could not be recovered is returned.
+fn recover_intermediate(
+    oracle: &mut impl FnMut(&[u8], &[u8]) -> bool,
+    previous: &[u8],
+    target: &[u8],
+) -> Result<Vec<u8>, usize> {
+    let block_size = target.len();
+    let mut intermediate = vec![0u8; block_size];
+    // Starting from the real previous block means the genuine padding of the last block is
+    // always one of the accepted guesses, which `is_false_positive` weeds out.
+    let mut forged = previous.to_vec();
+
+    for pad in 1..=block_size {
+        let position = block_size - pad;
+        for (f, i) in forged[position + 1..]
+            .iter_mut()
+            .zip(&intermediate[position + 1..])
+        {
+            *f = i ^ pad as u8;
+        }
+
+        let guess = (0..=u8::MAX).find(|guess| {
+            forged[position] = *guess;
+            oracle(&forged, target) && !is_false_positive(oracle, &forged, target, position)
+        });
+        match guess {
+            Some(guess) => intermediate[position] = guess ^ pad as u8,
+            None => return Err(position),
+        }
+    }
+    Ok(intermediate)
+}
+
+/// When looking for the final byte a longer pad, like `\x02\x02`, may be what the oracle accepted.
+/// Changing the second to last byte breaks any pad other than `\x01`.
+fn is_false_positive(
+    oracle: &mut impl FnMut(&[u8], &[u8]) -> bool,
+    forged: &[u8],
+    target: &[u8],
+    position: usize,
+) -> bool {
+    if position != forged.len() - 1 || position == 0 {
+        return false;
+    }
+    let mut tweaked = forged.to_vec();
+    tweaked[position - 1] ^= 0xff;
+    !oracle(&tweaked, target)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::base64;
+    use crate::block::Toy;
+    use crate::cbc;
+    use aes::cipher::KeyInit;
+    use yare::parameterized;
+
+    const KEY: &[u8; 16] = b"YELLOW SUBMARINE";
+    const IV: &[u8; 16] = b"an initial value";
+
+    fn oracle(iv: &[u8], ciphertext: &[u8]) -> bool {
+        cbc::cbc_decrypt_padded(KEY, iv, ciphertext).is_ok()
+    }
+
+    #[parameterized(
+    challenge_0 = { "MDAwMDAwTm93IHRoYXQgdGhlIHBhcnR5IGlzIGp1bXBpbmc=" },
+    challenge_1 = { "MDAwMDAxV2l0aCB0aGUgYmFzcyBraWNrZWQgaW4gYW5kIHRoZSBWZWdhJ3MgYXJlIHB1bXBpbic=" },
+    challenge_6 = { "MDAwMDA2QW5kIGEgaGlnaCBoYXQgd2l0aCBhIHNvdXBlZCB1cCB0ZW1wbw==" },
+    )]
+    fn challenge_17_strings(base_64: &str) {
+        let message = base64::decode(base_64).unwrap();
+        let ciphertext = cbc::encrypt_padded(KEY, IV, &message).unwrap();
+        let recovered = decrypt(oracle, IV, ciphertext).unwrap();
+        assert_eq!(recovered.unpadded().unwrap(), message);
+    }
+
+    // A final block ending in `\x02\x02` accepts the original byte as valid padding when
+    // looking for `\x01`, every length of pad should be recovered correctly.
+    #[parameterized(
+    pad_1 = { 15 },
+    pad_2 = { 14 },
+    pad_3 = { 13 },
+    pad_16 = { 16 },
+    )]
+    fn last_block_pad_lengths(length: usize) {
+        let message = vec![b'A'; length];
+        let ciphertext = cbc::encrypt_padded(KEY, IV, &message).unwrap();
+        let recovered = decrypt(oracle, IV, ciphertext).unwrap();
+        assert_eq!(recovered.unpadded().unwrap(), message);
+    }
+
+    #[test]
+    fn counts_queries() {
+        let ciphertext = cbc::encrypt_padded(KEY, IV, b"count the queries").unwrap();
+        let recovered = decrypt(oracle, IV, &ciphertext).unwrap();
+        // At least one query for every byte and at most one for every guess and false positive
+        // check
+        assert!(recovered.queries >= ciphertext.len());
+        assert!(recovered.queries <= ciphertext.len() * 257);
+    }
+
+    #[test]
+    fn toy_cipher_block_size() {
+        let toy = Toy::new_from_slice(b"toy key!").unwrap();
+        let toy_oracle = |iv: &[u8], ciphertext: &[u8]| {
+            cbc::cbc_decrypt_padded_with(&toy, iv, ciphertext).is_ok()
+        };
+        let ciphertext = cbc::encrypt_padded_with(&toy, b"an iv!!!", b"eight byte blocks").unwrap();
+        let recovered = decrypt(toy_oracle, b"an iv!!!", ciphertext).unwrap();
+        assert_eq!(recovered.unpadded().unwrap(), b"eight byte blocks");
+    }
+
+    #[test]
+    fn not_block_aligned() {
+        assert_eq!(
+            decrypt(oracle, IV, [0; 20]),
+            Err(PaddingOracleError::InvalidInput(
+                CipherError::NotBlockAligned {
+                    length: 20,
+                    block_size: 16
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn oracle_never_accepts() {
+        assert_eq!(
+            decrypt(|_: &[u8], _: &[u8]| false, IV, [0; 16]),
+            Err(PaddingOracleError::NoValidPadding {
+                block: 0,
+                position: 15
+            })
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//          Copyright Nick G 2023.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! Multi-threaded versions of the modes without a dependency between blocks, enabled with the
+//! `rayon` feature.
+//!
+//! Large buffers are split into groups of blocks which are handed to the single threaded in place
+//! functions on the rayon thread pool. Buffers smaller than [`PARALLEL_THRESHOLD`] are processed
+//! on the calling thread. The output is identical to the single threaded versions.
+
+use crate::block::{check_aligned, check_iv, Aes, CipherError};
+use crate::{cbc, ecb};
+use aes::cipher::{BlockDecrypt, BlockEncrypt};
+use rayon::prelude::*;
+
+/// Buffers shorter than this, in bytes, are not worth splitting across threads
+pub const PARALLEL_THRESHOLD: usize = 64 * 1024;
+
+/// The number of blocks given to each thread at a time
+const BLOCKS_PER_TASK: usize = 1024;
+
+const ALIGNED: &str = "the buffer was checked to be block aligned";
+
+/// Encrypt `data` in place with AES in ECB mode, the AES variant is chosen by the length of `key`
+pub fn ecb_encrypt_in_place(key: impl AsRef<[u8]>, data: &mut [u8]) -> Result<(), CipherError> {
+    let encryptor = Aes::new_checked(key.as_ref())?;
+    ecb_encrypt_in_place_with(&encryptor, data)
+}
+
+/// Encrypt `data` in place with any block cipher in ECB mode
+pub fn ecb_encrypt_in_place_with<C: BlockEncrypt + Sync>(
+    encryptor: &C,
+    data: &mut [u8],
+) -> Result<(), CipherError> {
+    check_aligned::<C>(data)?;
+    if data.len() < PARALLEL_THRESHOLD {
+        return ecb::encrypt_in_place_with(encryptor, data);
+    }
+    data.par_chunks_mut(C::block_size() * BLOCKS_PER_TASK)
+        .for_each(|chunk| ecb::encrypt_in_place_with(encryptor, chunk).expect(ALIGNED));
+    Ok(())
+}
+
+/// Decrypt `data` in place with AES in ECB mode, the AES variant is chosen by the length of `key`
+pub fn ecb_decrypt_in_place(key: impl AsRef<[u8]>, data: &mut [u8]) -> Result<(), CipherError> {
+    let decryptor = Aes::new_checked(key.as_ref())?;
+    ecb_decrypt_in_place_with(&decryptor, data)
+}
+
+/// Decrypt `data` in place with any block cipher in ECB mode
+pub fn ecb_decrypt_in_place_with<C: BlockDecrypt + Sync>(
+    decryptor: &C,
+    data: &mut [u8],
+) -> Result<(), CipherError> {
+    check_aligned::<C>(data)?;
+    if data.len() < PARALLEL_THRESHOLD {
+        return ecb::ecb_decrypt_in_place_with(decryptor, data);
+    }
+    data.par_chunks_mut(C::block_size() * BLOCKS_PER_TASK)
+        .for_each(|chunk| ecb::ecb_decrypt_in_place_with(decryptor, chunk).expect(ALIGNED));
+    Ok(())
+}
+
+/// Decrypt `data` in place with AES in CBC mode, the AES variant is chosen by the length of `key`
+pub fn cbc_decrypt_in_place(
+    key: impl AsRef<[u8]>,
+    iv: impl AsRef<[u8]>,
+    data: &mut [u8],
+) -> Result<(), CipherError> {
+    let decryptor = Aes::new_checked(key.as_ref())?;
+    cbc_decrypt_in_place_with(&decryptor, iv, data)
+}
+
+/// Decrypt `data` in place with any block cipher in CBC mode
+pub fn cbc_decrypt_in_place_with<C: BlockDecrypt + Sync>(
+    decryptor: &C,
+    iv: impl AsRef<[u8]>,
+    data: &mut [u8],
+) -> Result<(), CipherError> {
+    let iv = iv.as_ref();
+    check_iv::<C>(iv)?;
+    check_aligned::<C>(data)?;
+    if data.len() < PARALLEL_THRESHOLD {
+        return cbc::cbc_decrypt_in_place_with(decryptor, iv, data);
+    }
+
+    // Each task chains from the last ciphertext block of the task before it, which has to be
+    // copied out before any task overwrites it
+    let task_size = C::block_size() * BLOCKS_PER_TASK;
+    let ivs = std::iter::once(iv.to_vec())
+        .chain(
+            data.chunks(task_size)
+                .map(|chunk| chunk[chunk.len() - C::block_size()..].to_vec()),
+        )
+        .collect::<Vec<_>>();
+    data.par_chunks_mut(task_size)
+        .zip(ivs.par_iter())
+        .for_each(|(chunk, iv)| {
+            cbc::cbc_decrypt_in_place_with(decryptor, iv, chunk).expect(ALIGNED)
+        });
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::Toy;
+    use aes::cipher::KeyInit;
+    use yare::parameterized;
+
+    const KEY: &[u8; 16] = b"YELLOW SUBMARINE";
+    const IV: &[u8; 16] = b"an initial value";
+
+    fn data(length: usize) -> Vec<u8> {
+        (0..length).map(|i| (i * 7 % 256) as u8).collect()
+    }
+
+    #[parameterized(
+    small = { 160 },
+    threshold = { PARALLEL_THRESHOLD },
+    uneven_tasks = { PARALLEL_THRESHOLD * 3 + 16 * 5 },
+    )]
+    fn ecb_matches_single_threaded(length: usize) {
+        let raw = data(length);
+        let mut parallel = raw.clone();
+        ecb_encrypt_in_place(KEY, &mut parallel).unwrap();
+        assert_eq!(parallel, ecb::encrypt(KEY, &raw).unwrap());
+        ecb_decrypt_in_place(KEY, &mut parallel).unwrap();
+        assert_eq!(parallel, raw);
+    }
+
+    #[parameterized(
+    small = { 160 },
+    threshold = { PARALLEL_THRESHOLD },
+    uneven_tasks = { PARALLEL_THRESHOLD * 3 + 16 * 5 },
+    )]
+    fn cbc_matches_single_threaded(length: usize) {
+        let raw = data(length);
+        let mut encrypted = cbc::encrypt(KEY, IV, &raw).unwrap();
+        cbc_decrypt_in_place(KEY, IV, &mut encrypted).unwrap();
+        assert_eq!(encrypted, raw);
+    }
+
+    #[test]
+    fn toy_cipher() {
+        let toy = Toy::new_from_slice(b"toy key!").unwrap();
+        let raw = data(PARALLEL_THRESHOLD * 2 + 8);
+        let mut encrypted = cbc::encrypt_with(&toy, b"an iv!!!", &raw).unwrap();
+        cbc_decrypt_in_place_with(&toy, b"an iv!!!", &mut encrypted).unwrap();
+        assert_eq!(encrypted, raw);
+    }
+
+    #[test]
+    fn not_block_aligned() {
+        let expected = Err(CipherError::NotBlockAligned {
+            length: PARALLEL_THRESHOLD + 1,
+            block_size: 16,
+        });
+        let mut data = data(PARALLEL_THRESHOLD + 1);
+        assert_eq!(ecb_encrypt_in_place(KEY, &mut data), expected);
+        assert_eq!(ecb_decrypt_in_place(KEY, &mut data), expected);
+        assert_eq!(cbc_decrypt_in_place(KEY, IV, &mut data), expected);
+    }
+}
@@ -3,19 +3,98 @@
 //    (See accompanying file LICENSE or copy at
 //          https://www.boost.org/LICENSE_1_0.txt)
 
+//! PKCS#7 padding, https://datatracker.ietf.org/doc/html/rfc5652#section-6.3
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// Errors from removing PKCS#7 padding
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PaddingError {
+    /// The padded input is empty or not a multiple of the pad size
+    InvalidLength(usize),
+    /// The final byte is zero, which is never a valid pad
+    Zero,
+    /// The final byte is larger than the pad size
+    Oversized(u8),
+    /// Not every byte of the pad run has the pad value
+    Inconsistent(u8),
+    /// The pad size must be at least one byte
+    ZeroPadSize,
+}
+
+impl Display for PaddingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PaddingError::InvalidLength(length) => {
+                write!(f, "invalid length for padded input ({length})")
+            }
+            PaddingError::Zero => write!(f, "zero padding byte"),
+            PaddingError::Oversized(pad) => write!(f, "padding byte {pad} exceeds the pad size"),
+            PaddingError::Inconsistent(pad) => write!(f, "inconsistent padding run of {pad}"),
+            PaddingError::ZeroPadSize => write!(f, "the pad size must be at least one byte"),
+        }
+    }
+}
+
+impl Error for PaddingError {}
+
+/// `bytes` with PKCS#7 padding to a multiple of `pad_size`. Panics if `pad_size` is 0.
 pub fn pad(bytes: impl AsRef<[u8]>, pad_size: u8) -> Vec<u8> {
-    let bytes = bytes.as_ref();
+    let mut padded = bytes.as_ref().to_vec();
+    pad_in_place(&mut padded, pad_size);
+    padded
+}
+
+/// Append the padding to `bytes`, no allocation happens if `bytes` has a block of spare capacity.
+/// Panics if `pad_size` is 0.
+pub fn pad_in_place(bytes: &mut Vec<u8>, pad_size: u8) {
+    assert!(pad_size > 0, "the pad size must be at least one byte");
     let over_pad = bytes.len() % pad_size as usize;
     let pad_bytes = pad_size - over_pad as u8;
+    bytes.resize(bytes.len() + pad_bytes as usize, pad_bytes);
+}
 
-    let mut padded = bytes.to_vec();
-    padded.extend(vec![pad_bytes; pad_bytes as usize]);
-    padded
+/// Remove, and validate, the padding that [`pad`] added
+pub fn unpad(bytes: impl AsRef<[u8]>, pad_size: u8) -> Result<Vec<u8>, PaddingError> {
+    let bytes = bytes.as_ref();
+    Ok(bytes[..unpadded_length(bytes, pad_size)?].to_vec())
+}
+
+/// Remove, and validate, the padding that [`pad`] added by truncating `bytes`. `bytes` is left
+/// unchanged if the padding is invalid.
+pub fn unpad_in_place(bytes: &mut Vec<u8>, pad_size: u8) -> Result<(), PaddingError> {
+    bytes.truncate(unpadded_length(bytes, pad_size)?);
+    Ok(())
+}
+
+fn unpadded_length(bytes: &[u8], pad_size: u8) -> Result<usize, PaddingError> {
+    if pad_size == 0 {
+        return Err(PaddingError::ZeroPadSize);
+    }
+    if bytes.is_empty() || !bytes.len().is_multiple_of(pad_size as usize) {
+        return Err(PaddingError::InvalidLength(bytes.len()));
+    }
+
+    let pad_bytes = bytes[bytes.len() - 1];
+    if pad_bytes == 0 {
+        return Err(PaddingError::Zero);
+    }
+    if pad_bytes > pad_size {
+        return Err(PaddingError::Oversized(pad_bytes));
+    }
+
+    let unpadded_length = bytes.len() - pad_bytes as usize;
+    if bytes[unpadded_length..].iter().any(|b| *b != pad_bytes) {
+        return Err(PaddingError::Inconsistent(pad_bytes));
+    }
+    Ok(unpadded_length)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use yare::parameterized;
 
     #[test]
     fn one_byte_pad() {
@@ -34,4 +113,69 @@ mod test {
             b"YELLOW SUBMARINE\x04\x04\x04\x04"
         );
     }
+
+    #[parameterized(
+    one_byte = { b"123\x01", b"123" },
+    two_bytes = { b"55\x02\x02", b"55" },
+    full_block = { b"5555\x04\x04\x04\x04", b"5555" },
+    )]
+    fn unpad_valid(padded: &[u8], expected: &[u8]) {
+        assert_eq!(unpad(padded, 4), Ok(expected.to_vec()));
+    }
+
+    #[parameterized(
+    empty = { b"", PaddingError::InvalidLength(0) },
+    not_multiple = { b"12\x01", PaddingError::InvalidLength(3) },
+    zero = { b"123\x00", PaddingError::Zero },
+    oversized = { b"123\x05", PaddingError::Oversized(5) },
+    inconsistent = { b"12\x01\x03", PaddingError::Inconsistent(3) },
+    ice_ice_baby = { b"ICE ICE BABY\x01\x02\x03\x04", PaddingError::Inconsistent(4) },
+    )]
+    fn unpad_invalid(padded: &[u8], error: PaddingError) {
+        assert_eq!(unpad(padded, 4), Err(error));
+    }
+
+    #[test]
+    #[should_panic(expected = "the pad size must be at least one byte")]
+    fn pad_zero_pad_size() {
+        pad(b"123", 0);
+    }
+
+    #[test]
+    fn unpad_zero_pad_size() {
+        assert_eq!(unpad(b"123\x01", 0), Err(PaddingError::ZeroPadSize));
+        let mut bytes = b"123\x01".to_vec();
+        assert_eq!(
+            unpad_in_place(&mut bytes, 0),
+            Err(PaddingError::ZeroPadSize)
+        );
+    }
+
+    #[test]
+    fn pad_unpad_round_trip() {
+        let raw = b"ICE ICE BABY";
+        assert_eq!(unpad(pad(raw, 16), 16), Ok(raw.to_vec()));
+    }
+
+    #[test]
+    fn in_place_uses_spare_capacity() {
+        let mut bytes = Vec::with_capacity(16);
+        bytes.extend_from_slice(b"ICE ICE BABY");
+        let pointer = bytes.as_ptr();
+        pad_in_place(&mut bytes, 16);
+        assert_eq!(bytes, b"ICE ICE BABY\x04\x04\x04\x04");
+        assert_eq!(bytes.as_ptr(), pointer);
+        unpad_in_place(&mut bytes, 16).unwrap();
+        assert_eq!(bytes, b"ICE ICE BABY");
+    }
+
+    #[test]
+    fn unpad_in_place_invalid() {
+        let mut bytes = b"12\x01\x03".to_vec();
+        assert_eq!(
+            unpad_in_place(&mut bytes, 4),
+            Err(PaddingError::Inconsistent(3))
+        );
+        assert_eq!(bytes, b"12\x01\x03");
+    }
 }
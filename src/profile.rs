@@ -0,0 +1,472 @@
+//          Copyright Nick G 2023.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! ECB cut-and-paste, https://cryptopals.com/sets/2/challenges/13
+//!
+//! ECB encrypts every block on its own, so ciphertext blocks from different messages under the
+//! same key can be spliced together and still decrypt. By padding an attacker controlled field
+//! the interesting part of a message can be pushed onto a block boundary, cut off, and replaced
+//! with blocks of chosen plaintext encrypted by the oracle itself.
+
+use crate::block::CipherError;
+use crate::{ecb, pkcs};
+use rand::Rng;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+const BLOCK_SIZE: usize = 16;
+
+/// The `uid` given to every profile created by [`ProfileOracle`]
+pub const DEFAULT_UID: u32 = 10;
+
+/// Errors parsing `key=value&key=value` text
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ParseError {
+    /// The field, counted from 0, does not have exactly one `=`
+    InvalidField(usize),
+    /// A `%` at this byte offset is not followed by two hex digits
+    InvalidEscape(usize),
+    /// A key or value is not valid UTF-8 once unescaped
+    InvalidUtf8,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidField(field) => write!(f, "field {field} is not key=value"),
+            ParseError::InvalidEscape(index) => write!(f, "invalid escape at index {index}"),
+            ParseError::InvalidUtf8 => write!(f, "invalid UTF-8"),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+/// Errors turning a cookie into a [`Profile`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ProfileError {
+    Cipher(CipherError),
+    Parse(ParseError),
+    /// A required field was not present
+    MissingField(&'static str),
+    /// A field appeared more than once
+    DuplicateField(String),
+    /// The `uid` is not a number
+    InvalidUid(String),
+}
+
+impl Display for ProfileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileError::Cipher(error) => write!(f, "{error}"),
+            ProfileError::Parse(error) => write!(f, "{error}"),
+            ProfileError::MissingField(field) => write!(f, "missing field {field}"),
+            ProfileError::DuplicateField(field) => write!(f, "duplicate field {field}"),
+            ProfileError::InvalidUid(uid) => write!(f, "invalid uid {uid:?}"),
+        }
+    }
+}
+
+impl Error for ProfileError {}
+
+impl From<CipherError> for ProfileError {
+    fn from(error: CipherError) -> Self {
+        ProfileError::Cipher(error)
+    }
+}
+
+impl From<ParseError> for ProfileError {
+    fn from(error: ParseError) -> Self {
+        ProfileError::Parse(error)
+    }
+}
+
+/// Errors from the cut-and-paste forger
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ForgeError {
+    /// The oracle returned less ciphertext than the layout requires
+    CiphertextTooShort { needed: usize, length: usize },
+    /// The chosen plaintext must be a whole number of blocks
+    NotBlockAligned { length: usize, block_size: usize },
+}
+
+impl Display for ForgeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ForgeError::CiphertextTooShort { needed, length } => write!(
+                f,
+                "ciphertext length ({length}) is shorter than the {needed} bytes needed"
+            ),
+            ForgeError::NotBlockAligned { length, block_size } => write!(
+                f,
+                "plaintext length ({length}) is not a multiple of the block size ({block_size})"
+            ),
+        }
+    }
+}
+
+impl Error for ForgeError {}
+
+/// Encode `pairs` as `key=value&key=value`, escaping `%`, `&` and `=` in keys and values
+pub fn encode<K: AsRef<str>, V: AsRef<str>>(pairs: impl IntoIterator<Item = (K, V)>) -> String {
+    pairs
+        .into_iter()
+        .map(|(key, value)| format!("{}={}", escape(key.as_ref()), escape(value.as_ref())))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Parse `key=value&key=value` text made by [`encode`], keeping the order of the pairs
+pub fn parse(input: impl AsRef<[u8]>) -> Result<Vec<(String, String)>, ParseError> {
+    let input = input.as_ref();
+    if input.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut pairs = vec![];
+    let mut start = 0;
+    for (index, field) in input.split(|b| *b == b'&').enumerate() {
+        let parts = field.split(|b| *b == b'=').collect::<Vec<_>>();
+        let [key, value] = parts[..] else {
+            return Err(ParseError::InvalidField(index));
+        };
+        let value_start = start + key.len() + 1;
+        pairs.push((unescape(key, start)?, unescape(value, value_start)?));
+        start += field.len() + 1;
+    }
+    Ok(pairs)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '%' => escaped.push_str("%25"),
+            '&' => escaped.push_str("%26"),
+            '=' => escaped.push_str("%3D"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `offset` is where `text` starts in the whole input, for error reporting
+fn unescape(text: &[u8], offset: usize) -> Result<String, ParseError> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut index = 0;
+    while index < text.len() {
+        if text[index] == b'%' {
+            let byte = text
+                .get(index + 1..index + 3)
+                .and_then(|digits| std::str::from_utf8(digits).ok())
+                .filter(|digits| digits.bytes().all(|d| d.is_ascii_hexdigit()))
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or(ParseError::InvalidEscape(offset + index))?;
+            bytes.push(byte);
+            index += 3;
+        } else {
+            bytes.push(text[index]);
+            index += 1;
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| ParseError::InvalidUtf8)
+}
+
+/// A user profile, encoded as `email=..&uid=..&role=..`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Profile {
+    pub email: String,
+    pub uid: u32,
+    pub role: String,
+}
+
+impl Profile {
+    /// A `user` profile for `email`
+    pub fn for_email(email: impl Into<String>) -> Self {
+        Self {
+            email: email.into(),
+            uid: DEFAULT_UID,
+            role: "user".to_string(),
+        }
+    }
+
+    /// The encoded profile
+    pub fn encode(&self) -> String {
+        encode([
+            ("email", self.email.as_str()),
+            ("uid", &self.uid.to_string()),
+            ("role", &self.role),
+        ])
+    }
+
+    /// Parse an encoded profile, the fields may be in any order but each must appear once.
+    /// Unknown fields are ignored.
+    pub fn parse(input: impl AsRef<[u8]>) -> Result<Self, ProfileError> {
+        let (mut email, mut uid, mut role) = (None, None, None);
+        for (key, value) in parse(input)? {
+            let field = match key.as_str() {
+                "email" => &mut email,
+                "uid" => &mut uid,
+                "role" => &mut role,
+                _ => continue,
+            };
+            if field.replace(value).is_some() {
+                return Err(ProfileError::DuplicateField(key));
+            }
+        }
+        let uid = uid.ok_or(ProfileError::MissingField("uid"))?;
+        Ok(Self {
+            email: email.ok_or(ProfileError::MissingField("email"))?,
+            uid: uid.parse().map_err(|_| ProfileError::InvalidUid(uid))?,
+            role: role.ok_or(ProfileError::MissingField("role"))?,
+        })
+    }
+}
+
+/// Encrypts user profiles with AES-128-ECB
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ProfileOracle {
+    key: [u8; BLOCK_SIZE],
+}
+
+impl Default for ProfileOracle {
+    fn default() -> Self {
+        let mut rng = rand::thread_rng();
+        Self {
+            key: Rng::gen(&mut rng),
+        }
+    }
+}
+
+impl ProfileOracle {
+    /// An oracle with a random key
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An oracle with a known key
+    pub fn with_key(key: [u8; BLOCK_SIZE]) -> Self {
+        Self { key }
+    }
+
+    /// Encrypt the encoded `user` profile for `email`
+    pub fn encrypt(&self, email: &str) -> Vec<u8> {
+        let padded = pkcs::pad(Profile::for_email(email).encode(), BLOCK_SIZE as u8);
+        ecb::encrypt(self.key, padded).expect("the key is a valid length")
+    }
+
+    /// Decrypt and parse an encrypted profile
+    pub fn decrypt(&self, ciphertext: impl AsRef<[u8]>) -> Result<Profile, ProfileError> {
+        Profile::parse(ecb::ecb_decrypt_padded(self.key, ciphertext)?)
+    }
+}
+
+/// Splices ciphertext blocks from an ECB `oracle` which encrypts
+/// `prefix || attacker input || suffix`, where the prefix length is known.
+///
+/// The oracle may escape its input, so the filler and the chosen plaintext blocks should avoid
+/// any characters the oracle changes.
+#[derive(Clone, Debug)]
+pub struct CutAndPaste<O> {
+    oracle: O,
+    block_size: usize,
+    prefix_length: usize,
+    filler: u8,
+}
+
+impl<O> CutAndPaste<O>
+where
+    O: Fn(&[u8]) -> Vec<u8>,
+{
+    /// A forger for `oracle` with `prefix_length` bytes of plaintext before the attacker input
+    pub fn new(oracle: O, block_size: usize, prefix_length: usize) -> Self {
+        assert!(block_size > 0, "the block size must be at least one byte");
+        Self {
+            oracle,
+            block_size,
+            prefix_length,
+            filler: b'A',
+        }
+    }
+
+    /// The byte used to pad out the attacker input, defaults to `A`
+    pub fn filler(mut self, filler: u8) -> Self {
+        self.filler = filler;
+        self
+    }
+
+    /// The amount of filler which moves the start of the attacker input to a block boundary
+    fn alignment(&self) -> usize {
+        (self.block_size - self.prefix_length % self.block_size) % self.block_size
+    }
+
+    /// Encrypt whole blocks of chosen `plaintext` by aligning it to a block boundary in the
+    /// attacker input and cutting out its ciphertext
+    pub fn encrypt_blocks(&self, plaintext: impl AsRef<[u8]>) -> Result<Vec<u8>, ForgeError> {
+        let plaintext = plaintext.as_ref();
+        if !plaintext.len().is_multiple_of(self.block_size) {
+            return Err(ForgeError::NotBlockAligned {
+                length: plaintext.len(),
+                block_size: self.block_size,
+            });
+        }
+        let mut input = vec![self.filler; self.alignment()];
+        input.extend(plaintext);
+        let start = self.prefix_length + self.alignment();
+        self.cut(&input, start..start + plaintext.len())
+    }
+
+    /// The ciphertext up to `suffix_offset` bytes into the suffix, with at least `min_input`
+    /// bytes of filler so that point lands on a block boundary
+    pub fn head(&self, suffix_offset: usize, min_input: usize) -> Result<Vec<u8>, ForgeError> {
+        let unaligned = self.prefix_length + min_input + suffix_offset;
+        let extra = (self.block_size - unaligned % self.block_size) % self.block_size;
+        let input = vec![self.filler; min_input + extra];
+        self.cut(&input, 0..unaligned + extra)
+    }
+
+    /// Replace the plaintext from `suffix_offset` bytes into the suffix with `tail`, which is
+    /// PKCS#7 padded
+    pub fn forge(
+        &self,
+        suffix_offset: usize,
+        min_input: usize,
+        tail: impl AsRef<[u8]>,
+    ) -> Result<Vec<u8>, ForgeError> {
+        let mut forged = self.head(suffix_offset, min_input)?;
+        let tail = pkcs::pad(tail.as_ref(), self.block_size as u8);
+        forged.extend(self.encrypt_blocks(tail)?);
+        Ok(forged)
+    }
+
+    fn cut(&self, input: &[u8], range: std::ops::Range<usize>) -> Result<Vec<u8>, ForgeError> {
+        let encrypted = (self.oracle)(input);
+        encrypted
+            .get(range.clone())
+            .map(<[u8]>::to_vec)
+            .ok_or(ForgeError::CiphertextTooShort {
+                needed: range.end,
+                length: encrypted.len(),
+            })
+    }
+}
+
+/// Forge an encrypted profile with the `admin` role using only `oracle`
+pub fn forge_admin(oracle: &ProfileOracle) -> Result<Vec<u8>, ForgeError> {
+    let forger = CutAndPaste::new(
+        |email: &[u8]| oracle.encrypt(&String::from_utf8_lossy(email)),
+        BLOCK_SIZE,
+        "email=".len(),
+    );
+    let suffix_offset = format!("&uid={DEFAULT_UID}&role=").len();
+    forger.forge(suffix_offset, 1, b"admin")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use yare::parameterized;
+
+    const KEY: [u8; 16] = *b"YELLOW SUBMARINE";
+
+    #[test]
+    fn encode_profile() {
+        assert_eq!(
+            Profile::for_email("foo@bar.com").encode(),
+            "email=foo@bar.com&uid=10&role=user"
+        );
+    }
+
+    #[test]
+    fn metacharacters_are_escaped() {
+        let profile = Profile::for_email("foo@bar.com&role=admin");
+        let encoded = profile.encode();
+        assert_eq!(encoded, "email=foo@bar.com%26role%3Dadmin&uid=10&role=user");
+        assert_eq!(Profile::parse(encoded), Ok(profile));
+    }
+
+    #[parameterized(
+    empty = { "", vec![] },
+    single = { "a=b", vec![("a", "b")] },
+    empty_value = { "a=&b=c", vec![("a", ""), ("b", "c")] },
+    escaped = { "k%3D=100%25%26", vec![("k=", "100%&")] },
+    )]
+    fn parse_pairs(input: &str, expected: Vec<(&str, &str)>) {
+        let expected = expected
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(parse(input), Ok(expected.clone()));
+        assert_eq!(encode(expected), input);
+    }
+
+    #[parameterized(
+    no_equals = { "a=b&c", ParseError::InvalidField(1) },
+    two_equals = { "a=b=c", ParseError::InvalidField(0) },
+    short_escape = { "a=b&c=%2", ParseError::InvalidEscape(6) },
+    bad_escape = { "a=%zz", ParseError::InvalidEscape(2) },
+    non_utf8 = { "a=%ff", ParseError::InvalidUtf8 },
+    )]
+    fn parse_errors(input: &str, error: ParseError) {
+        assert_eq!(parse(input), Err(error));
+    }
+
+    #[parameterized(
+    missing = { "email=a&uid=1", ProfileError::MissingField("role") },
+    duplicate = { "email=a&uid=1&role=user&role=admin", ProfileError::DuplicateField("role".to_string()) },
+    uid = { "email=a&uid=x&role=user", ProfileError::InvalidUid("x".to_string()) },
+    )]
+    fn profile_errors(input: &str, error: ProfileError) {
+        assert_eq!(Profile::parse(input), Err(error));
+    }
+
+    #[test]
+    fn oracle_round_trip() {
+        let oracle = ProfileOracle::with_key(KEY);
+        let profile = oracle.decrypt(oracle.encrypt("foo@bar.com")).unwrap();
+        assert_eq!(profile, Profile::for_email("foo@bar.com"));
+    }
+
+    #[test]
+    fn forge_admin_profile() {
+        let oracle = ProfileOracle::new();
+        let forged = forge_admin(&oracle).unwrap();
+        let profile = oracle.decrypt(forged).unwrap();
+        assert_eq!(profile.role, "admin");
+        assert_eq!(profile.uid, DEFAULT_UID);
+    }
+
+    #[test]
+    fn head_ends_on_block_boundary() {
+        let oracle = ProfileOracle::with_key(KEY);
+        let forger = CutAndPaste::new(
+            |email: &[u8]| oracle.encrypt(&String::from_utf8_lossy(email)),
+            16,
+            6,
+        )
+        .filler(b'x');
+        let head = forger.head(13, 0).unwrap();
+        let plaintext = ecb::ecb_decrypt(KEY, &head).unwrap();
+        assert_eq!(plaintext, b"email=xxxxxxxxxxxxx&uid=10&role=");
+    }
+
+    #[test]
+    fn ciphertext_too_short() {
+        let forger = CutAndPaste::new(|_: &[u8]| vec![0; 16], 16, 6);
+        assert_eq!(
+            forger.head(13, 0),
+            Err(ForgeError::CiphertextTooShort {
+                needed: 32,
+                length: 16
+            })
+        );
+        assert_eq!(
+            forger.encrypt_blocks(b"admin"),
+            Err(ForgeError::NotBlockAligned {
+                length: 5,
+                block_size: 16
+            })
+        );
+    }
+}
@@ -0,0 +1,355 @@
+// Copyright 2022 Nick G.
+//
+// Use of this source code is governed by an MIT-style
+// license that can be found in the LICENSE file or at
+// https://opensource.org/licenses/MIT.
+
+//! Scoring how closely some bytes resemble English text.
+//!
+//! All of the scorers work on bytes rather than `str` so that candidate plaintexts do not need to
+//! be valid UTF-8. Scores are only meaningful relative to other scores from the same scorer,
+//! higher means more likely to be English. Scorers can be combined by summing them as a tuple,
+//! `(NgramLogLikelihood::english_bigrams(), PrintablePenalty::default())`.
+
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::{fs, io};
+
+static WEIGHTS: &str = "ETAOIN SHRDLU";
+
+static ENGLISH_CORPUS: &str = include_str!("../assets/english.txt");
+static ENGLISH_UNIGRAMS: Lazy<Frequencies> =
+    Lazy::new(|| Frequencies::from_corpus(ENGLISH_CORPUS, 1));
+static ENGLISH_BIGRAMS: Lazy<Frequencies> =
+    Lazy::new(|| Frequencies::from_corpus(ENGLISH_CORPUS, 2));
+static ENGLISH_TRIGRAMS: Lazy<Frequencies> =
+    Lazy::new(|| Frequencies::from_corpus(ENGLISH_CORPUS, 3));
+
+/// The probability given to n-grams which never appeared in the corpus
+const UNSEEN_PROBABILITY: f32 = 1e-5;
+
+/// Scores how English like some text is
+pub trait Scorer {
+    /// The score for `text`, higher is more likely to be English
+    fn score(&self, text: &[u8]) -> f32;
+}
+
+impl<S: Scorer + ?Sized> Scorer for &S {
+    fn score(&self, text: &[u8]) -> f32 {
+        (**self).score(text)
+    }
+}
+
+impl<S: Scorer + ?Sized> Scorer for Box<S> {
+    fn score(&self, text: &[u8]) -> f32 {
+        (**self).score(text)
+    }
+}
+
+impl<A: Scorer, B: Scorer> Scorer for (A, B) {
+    fn score(&self, text: &[u8]) -> f32 {
+        self.0.score(text) + self.1.score(text)
+    }
+}
+
+/// Fold case and whitespace so that `The` and `the ` share n-grams. Bytes which are not
+/// printable ASCII all become `0`.
+fn normalize(byte: u8) -> u8 {
+    match byte {
+        b'A'..=b'Z' => byte.to_ascii_lowercase(),
+        b'\t' | b'\n' | b'\r' | b' ' => b' ',
+        b'!'..=b'~' => byte,
+        _ => 0,
+    }
+}
+
+fn is_printable(byte: u8) -> bool {
+    matches!(byte, b'\t' | b'\n' | b'\r' | b' '..=b'~')
+}
+
+/// The relative frequency of every n-gram in a corpus
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frequencies {
+    n: usize,
+    probabilities: BTreeMap<Vec<u8>, f32>,
+}
+
+impl Frequencies {
+    /// Count the n-grams of length `n` in `corpus`, runs of whitespace count as a single space
+    pub fn from_corpus(corpus: impl AsRef<[u8]>, n: usize) -> Self {
+        assert!(n > 0, "n-grams must be at least one byte");
+        let mut normalized: Vec<u8> = vec![];
+        for byte in corpus.as_ref().iter().copied().map(normalize) {
+            if byte != b' ' || normalized.last() != Some(&b' ') {
+                normalized.push(byte);
+            }
+        }
+
+        let mut counts = BTreeMap::new();
+        for gram in normalized.windows(n) {
+            *counts.entry(gram.to_vec()).or_insert(0usize) += 1;
+        }
+        let total = normalized.len().saturating_sub(n - 1).max(1) as f32;
+        let probabilities = counts
+            .into_iter()
+            .map(|(gram, count)| (gram, count as f32 / total))
+            .collect();
+        Self { n, probabilities }
+    }
+
+    /// Count the n-grams of length `n` in the file at `path`
+    pub fn from_file(path: impl AsRef<Path>, n: usize) -> io::Result<Self> {
+        Ok(Self::from_corpus(fs::read(path)?, n))
+    }
+
+    /// The length of the n-grams
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    /// The relative frequency of `gram`, `gram` is case insensitive
+    pub fn probability(&self, gram: &[u8]) -> Option<f32> {
+        let gram = gram.iter().copied().map(normalize).collect::<Vec<_>>();
+        self.probabilities.get(&gram).copied()
+    }
+
+    fn grams<'a>(&self, normalized: &'a [u8]) -> impl Iterator<Item = &'a [u8]> {
+        normalized.windows(self.n)
+    }
+}
+
+/// Pearson's chi-squared test of the n-gram counts against the expected frequencies, negated so
+/// a closer fit scores higher. Best suited to single byte frequencies where the text may not be
+/// contiguous, like a column of repeating key XOR.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChiSquared {
+    expected: Frequencies,
+}
+
+impl ChiSquared {
+    pub fn new(expected: Frequencies) -> Self {
+        Self { expected }
+    }
+
+    /// English letter, space and punctuation frequencies
+    pub fn english() -> Self {
+        Self::new(ENGLISH_UNIGRAMS.clone())
+    }
+}
+
+impl Default for ChiSquared {
+    fn default() -> Self {
+        Self::english()
+    }
+}
+
+impl Scorer for ChiSquared {
+    fn score(&self, text: &[u8]) -> f32 {
+        let normalized = text.iter().copied().map(normalize).collect::<Vec<_>>();
+        let mut observed = BTreeMap::new();
+        for gram in self.expected.grams(&normalized) {
+            *observed.entry(gram).or_insert(0f32) += 1.0;
+        }
+        let total = observed.values().sum::<f32>();
+        if total == 0.0 {
+            return 0.0;
+        }
+
+        let expected_only = self
+            .expected
+            .probabilities
+            .iter()
+            .filter(|(gram, _)| !observed.contains_key(gram.as_slice()))
+            .map(|(_, probability)| probability * total)
+            .sum::<f32>();
+        let observed_terms = observed
+            .iter()
+            .map(|(gram, count)| {
+                let probability = self.expected.probabilities.get(*gram);
+                let expected = probability.unwrap_or(&UNSEEN_PROBABILITY) * total;
+                (count - expected).powi(2) / expected
+            })
+            .sum::<f32>();
+        // For a gram which was never observed `(0 - e)^2 / e` is just `e`
+        -(expected_only + observed_terms) / total
+    }
+}
+
+/// The mean log probability of each n-gram in the text
+#[derive(Clone, Debug, PartialEq)]
+pub struct NgramLogLikelihood {
+    frequencies: Frequencies,
+}
+
+impl NgramLogLikelihood {
+    pub fn new(frequencies: Frequencies) -> Self {
+        Self { frequencies }
+    }
+
+    /// Pairs of English characters
+    pub fn english_bigrams() -> Self {
+        Self::new(ENGLISH_BIGRAMS.clone())
+    }
+
+    /// Triples of English characters
+    pub fn english_trigrams() -> Self {
+        Self::new(ENGLISH_TRIGRAMS.clone())
+    }
+}
+
+impl Default for NgramLogLikelihood {
+    fn default() -> Self {
+        Self::english_bigrams()
+    }
+}
+
+impl Scorer for NgramLogLikelihood {
+    fn score(&self, text: &[u8]) -> f32 {
+        let normalized = text.iter().copied().map(normalize).collect::<Vec<_>>();
+        let log_probabilities = self
+            .frequencies
+            .grams(&normalized)
+            .map(|gram| {
+                let probability = self.frequencies.probabilities.get(gram);
+                probability.unwrap_or(&UNSEEN_PROBABILITY).ln()
+            })
+            .collect::<Vec<_>>();
+        match log_probabilities.len() {
+            0 => 0.0,
+            count => log_probabilities.iter().sum::<f32>() / count as f32,
+        }
+    }
+}
+
+/// Penalizes the fraction of bytes which are not printable ASCII. On its own it only separates
+/// text from binary, it is meant to be combined with one of the other scorers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PrintablePenalty {
+    penalty: f32,
+}
+
+impl PrintablePenalty {
+    /// `penalty` is the score taken away when every byte is unprintable
+    pub fn new(penalty: f32) -> Self {
+        Self { penalty }
+    }
+}
+
+impl Default for PrintablePenalty {
+    fn default() -> Self {
+        Self::new(100.0)
+    }
+}
+
+impl Scorer for PrintablePenalty {
+    fn score(&self, text: &[u8]) -> f32 {
+        if text.is_empty() {
+            return 0.0;
+        }
+        let unprintable = text.iter().filter(|b| !is_printable(**b)).count();
+        -self.penalty * unprintable as f32 / text.len() as f32
+    }
+}
+
+/// The original scoring, by the position of each character in "ETAOIN SHRDLU"
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EtaoinShrdlu;
+
+impl Scorer for EtaoinShrdlu {
+    fn score(&self, text: &[u8]) -> f32 {
+        weight_characters(&String::from_utf8_lossy(text))
+    }
+}
+
+fn weight_characters(message: &str) -> f32 {
+    let total_weight: usize = message
+        .to_uppercase()
+        .chars()
+        .map(|c| match WEIGHTS.chars().rev().position(|w| w == c) {
+            Some(pos) => pos + 1,
+            None => 0,
+        })
+        .sum();
+    total_weight as f32 / message.len() as f32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use yare::parameterized;
+
+    #[test]
+    fn weight_foo() {
+        assert_eq!(weight_characters("foo"), 6.6666665f32);
+    }
+
+    #[test]
+    fn weight_happy() {
+        assert_eq!(weight_characters("happy"), 3.2f32);
+    }
+
+    #[test]
+    fn frequencies_from_corpus() {
+        let frequencies = Frequencies::from_corpus("Ab  ab\nab", 2);
+        assert_eq!(frequencies.n(), 2);
+        // "ab ab ab" has 7 bigrams, 3 of which are "ab"
+        assert_eq!(frequencies.probability(b"AB"), Some(3.0 / 7.0));
+        assert_eq!(frequencies.probability(b"b "), Some(2.0 / 7.0));
+        assert_eq!(frequencies.probability(b"ba"), None);
+    }
+
+    #[test]
+    fn frequencies_from_file() {
+        let frequencies = Frequencies::from_file("assets/english.txt", 1).unwrap();
+        assert_eq!(frequencies, *ENGLISH_UNIGRAMS);
+        assert!(Frequencies::from_file("assets/missing.txt", 1).is_err());
+    }
+
+    #[parameterized(
+    chi_squared = { Box::new(ChiSquared::english()) },
+    bigrams = { Box::new(NgramLogLikelihood::english_bigrams()) },
+    trigrams = { Box::new(NgramLogLikelihood::english_trigrams()) },
+    printable = { Box::new(PrintablePenalty::default()) },
+    )]
+    fn english_beats_noise(scorer: Box<dyn Scorer>) {
+        let english = b"the quick brown fox jumps over the lazy dog";
+        let noise = english.iter().map(|b| b ^ 0x9c).collect::<Vec<_>>();
+        assert!(scorer.score(english) > scorer.score(&noise));
+    }
+
+    #[parameterized(
+    chi_squared = { Box::new(ChiSquared::english()) },
+    bigrams = { Box::new(NgramLogLikelihood::english_bigrams()) },
+    trigrams = { Box::new(NgramLogLikelihood::english_trigrams()) },
+    )]
+    fn english_beats_shifted_letters(scorer: Box<dyn Scorer>) {
+        let english = b"it was late in the afternoon when the rain stopped";
+        let shifted = english
+            .iter()
+            .map(|b| match b {
+                b'a'..=b'z' => (b - b'a' + 13) % 26 + b'a',
+                _ => *b,
+            })
+            .collect::<Vec<_>>();
+        assert!(scorer.score(english) > scorer.score(&shifted));
+    }
+
+    #[test]
+    fn printable_penalty() {
+        let scorer = PrintablePenalty::new(10.0);
+        assert_eq!(scorer.score(b"text\n"), 0.0);
+        assert_eq!(scorer.score(b"te\x00\xff"), -5.0);
+        assert_eq!(scorer.score(b""), 0.0);
+    }
+
+    #[test]
+    fn tuple_sums_scores() {
+        let text = b"some \x00 text";
+        let combined = (EtaoinShrdlu, PrintablePenalty::default());
+        assert_eq!(
+            combined.score(text),
+            EtaoinShrdlu.score(text) + PrintablePenalty::default().score(text)
+        );
+    }
+}
@@ -0,0 +1,430 @@
+//          Copyright Nick G 2023.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! SHA-1, with the internal state exposed for length extension,
+//! https://cryptopals.com/sets/4/challenges/28 and https://cryptopals.com/sets/4/challenges/29
+//!
+//! A SHA-1 digest is the complete internal state after the padded message, so hashing can be
+//! resumed from a digest. For a `SHA1(key || message)` MAC this gives the MAC of
+//! `message || padding || extension` without knowing the key, only its length, which can be
+//! guessed.
+
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::ops::RangeInclusive;
+
+/// The size of a SHA-1 digest in bytes
+pub const DIGEST_SIZE: usize = 20;
+
+/// The size of a SHA-1 message block in bytes
+pub const BLOCK_SIZE: usize = 64;
+
+/// The initial SHA-1 registers
+pub const INITIAL_STATE: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+/// The key lengths tried by [`forge`] when none are given
+pub const DEFAULT_KEY_LENGTHS: RangeInclusive<usize> = 0..=64;
+
+/// Errors from resuming SHA-1 or extending a message
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Sha1Error {
+    /// Hashing can only resume after whole blocks, the length in bytes is not a multiple of the
+    /// block size
+    PartialBlock(u64),
+    /// The key and message together are longer than can be counted
+    LengthOverflow,
+}
+
+impl Display for Sha1Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Sha1Error::PartialBlock(length) => write!(
+                f,
+                "length ({length}) is not a multiple of the block size ({BLOCK_SIZE})"
+            ),
+            Sha1Error::LengthOverflow => write!(f, "the message length overflows"),
+        }
+    }
+}
+
+impl Error for Sha1Error {}
+
+/// An incremental SHA-1 hasher
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Sha1 {
+    state: [u32; 5],
+    length: u64,
+    buffer: Vec<u8>,
+}
+
+impl Default for Sha1 {
+    fn default() -> Self {
+        Self {
+            state: INITIAL_STATE,
+            length: 0,
+            buffer: Vec::with_capacity(BLOCK_SIZE),
+        }
+    }
+}
+
+impl Sha1 {
+    /// A hasher at the start of a message
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resume hashing from `state` after `length` bytes. The bytes already hashed must fill whole
+    /// blocks, as they do after the padding of an earlier message, so `length` must be a multiple
+    /// of the block size.
+    pub fn from_state(state: [u32; 5], length: u64) -> Result<Self, Sha1Error> {
+        if !length.is_multiple_of(BLOCK_SIZE as u64) {
+            return Err(Sha1Error::PartialBlock(length));
+        }
+        Ok(Self {
+            state,
+            length,
+            ..Self::default()
+        })
+    }
+
+    /// Resume hashing from a `digest`, after `length` bytes including the padding
+    pub fn from_digest(digest: &[u8; DIGEST_SIZE], length: u64) -> Result<Self, Sha1Error> {
+        Self::from_state(state_from_digest(digest), length)
+    }
+
+    /// The registers after the last whole block
+    pub fn state(&self) -> [u32; 5] {
+        self.state
+    }
+
+    /// The number of bytes hashed so far
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+
+    /// Hash more of the message
+    pub fn update(&mut self, data: impl AsRef<[u8]>) -> &mut Self {
+        let mut data = data.as_ref();
+        self.length += data.len() as u64;
+        if !self.buffer.is_empty() {
+            let needed = (BLOCK_SIZE - self.buffer.len()).min(data.len());
+            self.buffer.extend(&data[..needed]);
+            data = &data[needed..];
+            if self.buffer.len() < BLOCK_SIZE {
+                return self;
+            }
+            let block = std::mem::take(&mut self.buffer);
+            compress(&mut self.state, &block);
+        }
+        let mut blocks = data.chunks_exact(BLOCK_SIZE);
+        for block in &mut blocks {
+            compress(&mut self.state, block);
+        }
+        self.buffer.extend(blocks.remainder());
+        self
+    }
+
+    /// Pad the message and return the digest
+    pub fn finalize(&self) -> [u8; DIGEST_SIZE] {
+        let mut state = self.state;
+        let tail = [self.buffer.as_slice(), &padding(self.length)].concat();
+        for block in tail.chunks_exact(BLOCK_SIZE) {
+            compress(&mut state, block);
+        }
+        digest_from_state(&state)
+    }
+}
+
+/// The SHA-1 digest of `data`
+pub fn digest(data: impl AsRef<[u8]>) -> [u8; DIGEST_SIZE] {
+    Sha1::new().update(data).finalize()
+}
+
+/// The padding SHA-1 appends to a message of `length` bytes, a `1` bit, zeros to 8 bytes short
+/// of a block and the message length in bits
+pub fn padding(length: u64) -> Vec<u8> {
+    let zeros = (BLOCK_SIZE * 2 - 9 - (length as usize % BLOCK_SIZE)) % BLOCK_SIZE;
+    let mut padding = Vec::with_capacity(zeros + 9);
+    padding.push(0x80);
+    padding.resize(zeros + 1, 0);
+    padding.extend(length.wrapping_mul(8).to_be_bytes());
+    padding
+}
+
+/// The registers a digest was made from
+pub fn state_from_digest(digest: &[u8; DIGEST_SIZE]) -> [u32; 5] {
+    let mut state = [0; 5];
+    for (register, bytes) in state.iter_mut().zip(digest.chunks_exact(4)) {
+        *register = u32::from_be_bytes(bytes.try_into().unwrap());
+    }
+    state
+}
+
+fn digest_from_state(state: &[u32; 5]) -> [u8; DIGEST_SIZE] {
+    let mut digest = [0; DIGEST_SIZE];
+    for (bytes, register) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&register.to_be_bytes());
+    }
+    digest
+}
+
+fn compress(state: &mut [u32; 5], block: &[u8]) {
+    let mut w = [0u32; 80];
+    for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes(bytes.try_into().unwrap());
+    }
+    for i in 16..80 {
+        w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e] = *state;
+    for (i, word) in w.iter().enumerate() {
+        let (f, k) = match i {
+            0..=19 => ((b & c) | (!b & d), 0x5A827999),
+            20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+            40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+            _ => (b ^ c ^ d, 0xCA62C1D6),
+        };
+        let temp = a
+            .rotate_left(5)
+            .wrapping_add(f)
+            .wrapping_add(e)
+            .wrapping_add(k)
+            .wrapping_add(*word);
+        e = d;
+        d = c;
+        c = b.rotate_left(30);
+        b = a;
+        a = temp;
+    }
+    for (register, value) in state.iter_mut().zip([a, b, c, d, e]) {
+        *register = register.wrapping_add(value);
+    }
+}
+
+/// The secret-prefix MAC, `SHA1(key || message)`
+pub fn mac(key: impl AsRef<[u8]>, message: impl AsRef<[u8]>) -> [u8; DIGEST_SIZE] {
+    Sha1::new().update(key).update(message).finalize()
+}
+
+/// Whether `tag` is the secret-prefix MAC of `message`
+pub fn verify(key: impl AsRef<[u8]>, message: impl AsRef<[u8]>, tag: &[u8; DIGEST_SIZE]) -> bool {
+    let expected = mac(key, message);
+    expected
+        .iter()
+        .zip(tag)
+        .fold(0, |difference, (a, b)| difference | (a ^ b))
+        == 0
+}
+
+/// A message and MAC made by length extension
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Extended {
+    /// The original message, its padding and the extension
+    pub message: Vec<u8>,
+    /// The MAC of `message` under the unknown key
+    pub tag: [u8; DIGEST_SIZE],
+    /// The key length the forgery assumed
+    pub key_length: usize,
+}
+
+/// Append `extension` to `message`, whose secret-prefix MAC is `tag`, assuming the key is
+/// `key_length` bytes long
+pub fn extend(
+    message: impl AsRef<[u8]>,
+    tag: &[u8; DIGEST_SIZE],
+    key_length: usize,
+    extension: impl AsRef<[u8]>,
+) -> Result<Extended, Sha1Error> {
+    let message = message.as_ref();
+    let length = key_length
+        .checked_add(message.len())
+        .ok_or(Sha1Error::LengthOverflow)? as u64;
+    let glue = padding(length);
+    let hashed = length
+        .checked_add(glue.len() as u64)
+        .ok_or(Sha1Error::LengthOverflow)?;
+    let tag = Sha1::from_digest(tag, hashed)?
+        .update(extension.as_ref())
+        .finalize();
+    Ok(Extended {
+        message: [message, &glue, extension.as_ref()].concat(),
+        tag,
+        key_length,
+    })
+}
+
+/// Forge a MAC for `message` with `extension` appended, trying each of `key_lengths` until the
+/// `verify` oracle accepts the forgery. `None` when no key length works.
+pub fn forge<V>(
+    verify: V,
+    message: impl AsRef<[u8]>,
+    tag: &[u8; DIGEST_SIZE],
+    extension: impl AsRef<[u8]>,
+    key_lengths: impl IntoIterator<Item = usize>,
+) -> Result<Option<Extended>, Sha1Error>
+where
+    V: Fn(&[u8], &[u8; DIGEST_SIZE]) -> bool,
+{
+    let message = message.as_ref();
+    let extension = extension.as_ref();
+    for key_length in key_lengths {
+        let forged = extend(message, tag, key_length, extension)?;
+        if verify(&forged.message, &forged.tag) {
+            return Ok(Some(forged));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hex::Hex;
+    use yare::parameterized;
+
+    fn hex(digest: [u8; DIGEST_SIZE]) -> String {
+        String::from(&Hex::from(digest.as_slice()))
+    }
+
+    #[parameterized(
+    empty = { b"", "da39a3ee5e6b4b0d3255bfef95601890afd80709" },
+    abc = { b"abc", "a9993e364706816aba3e25717850c26c9cd0d89d" },
+    two_blocks = {
+        b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+        "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+    },
+    fox = {
+        b"The quick brown fox jumps over the lazy dog",
+        "2fd4e1c67a2d28fced849ee1bb76e7391b93eb12"
+    },
+    long = {
+        b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu",
+        "a49b2446a02c645bf419f995b67091253a04a259"
+    },
+    )]
+    fn known_vectors(message: &[u8], expected: &str) {
+        assert_eq!(hex(digest(message)), expected);
+    }
+
+    #[test]
+    fn million_a() {
+        let mut hasher = Sha1::new();
+        for _ in 0..1000 {
+            hasher.update([b'a'; 1000]);
+        }
+        assert_eq!(
+            hex(hasher.finalize()),
+            "34aa973cd4c4daa4f61eeb2bdbad27316534016f"
+        );
+    }
+
+    #[test]
+    fn uneven_updates() {
+        let message = (0..=255u8).cycle().take(1000).collect::<Vec<_>>();
+        for size in [1, 7, 63, 64, 65, 200] {
+            let mut hasher = Sha1::new();
+            message.chunks(size).for_each(|chunk| {
+                hasher.update(chunk);
+            });
+            assert_eq!(hasher.length(), 1000);
+            assert_eq!(hasher.finalize(), digest(&message), "chunk size {size}");
+        }
+    }
+
+    #[parameterized(
+    empty = { 0, 64 },
+    short = { 3, 61 },
+    fits = { 55, 9 },
+    spills = { 56, 72 },
+    full = { 64, 64 },
+    )]
+    fn padding_length(length: u64, padded: usize) {
+        let padding = padding(length);
+        assert_eq!(padding.len(), padded);
+        assert_eq!(padding[0], 0x80);
+        assert_eq!(padding[padded - 8..], (length * 8).to_be_bytes());
+        assert_eq!((length as usize + padded) % BLOCK_SIZE, 0);
+    }
+
+    #[test]
+    fn resume_from_state() {
+        let first = [b'x'; 128];
+        let mut hasher = Sha1::new();
+        hasher.update(first);
+        let resumed = Sha1::from_state(hasher.state(), hasher.length())
+            .unwrap()
+            .update(b"more")
+            .finalize();
+        assert_eq!(resumed, digest([&first[..], b"more"].concat()));
+
+        let digest_state = state_from_digest(&digest(b"abc"));
+        let padded = [b"abc".as_slice(), &padding(3)].concat();
+        let mut hasher = Sha1::new();
+        hasher.update(&padded);
+        assert_eq!(hasher.state(), digest_state);
+    }
+
+    #[test]
+    fn resume_mid_block() {
+        assert_eq!(
+            Sha1::from_state(INITIAL_STATE, 3),
+            Err(Sha1Error::PartialBlock(3))
+        );
+        assert_eq!(
+            Sha1::from_digest(&digest(b"abc"), 65),
+            Err(Sha1Error::PartialBlock(65))
+        );
+        assert_eq!(
+            extend(b"message", &digest(b"abc"), usize::MAX, b"more"),
+            Err(Sha1Error::LengthOverflow)
+        );
+    }
+
+    #[test]
+    fn secret_prefix_mac() {
+        let tag = mac(b"key", b"message");
+        assert_eq!(tag, digest(b"keymessage"));
+        assert!(verify(b"key", b"message", &tag));
+        assert!(!verify(b"key", b"massage", &tag));
+    }
+
+    #[test]
+    fn length_extension() {
+        let key = b"unknown key";
+        let message =
+            b"comment1=cooking%20MCs;userdata=foo;comment2=%20like%20a%20pound%20of%20bacon";
+        let tag = mac(key, message);
+        let verify_oracle = |message: &[u8], tag: &[u8; DIGEST_SIZE]| verify(key, message, tag);
+
+        let forged = forge(
+            verify_oracle,
+            message,
+            &tag,
+            b";admin=true",
+            DEFAULT_KEY_LENGTHS,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(forged.key_length, key.len());
+        assert!(forged.message.starts_with(message));
+        assert!(forged.message.ends_with(b";admin=true"));
+        assert_eq!(forged.tag, mac(key, &forged.message));
+    }
+
+    #[test]
+    fn key_length_out_of_range() {
+        let key = [0; 32];
+        let tag = mac(key, b"message");
+        let forged = forge(
+            |message, tag| verify(key, message, tag),
+            b"message",
+            &tag,
+            b"more",
+            0..=16,
+        );
+        assert_eq!(forged, Ok(None));
+    }
+}
@@ -0,0 +1,572 @@
+//          Copyright Nick G 2023.
+// Distributed under the Boost Software License, Version 1.0.
+//    (See accompanying file LICENSE or copy at
+//          https://www.boost.org/LICENSE_1_0.txt)
+
+//! Streaming encryption and decryption over [`Write`] and [`Read`].
+//!
+//! [`EncryptWriter`] encrypts everything written to it and [`DecryptReader`] decrypts everything
+//! read through it, only ever holding a few blocks in memory. The block modes, [`Ecb`] and
+//! [`Cbc`], apply PKCS#7 padding when the writer is finished and remove it from the final block
+//! read. [`RepeatingXor`] is not padded.
+
+use crate::block::{check_iv, Aes, CipherError};
+use crate::{cbc, ecb, pkcs};
+use aes::cipher::{Block, BlockDecrypt, BlockEncrypt, BlockSizeUser};
+use std::io::{self, Read, Write};
+
+/// The size of the reads from the inner reader of a [`DecryptReader`]
+const CHUNK_SIZE: usize = 8192;
+
+const WHOLE_BLOCKS: &str = "modes are only given whole blocks";
+
+/// A cipher mode which can be applied to a stream one chunk at a time
+pub trait Mode {
+    /// The number of bytes which must be processed together
+    fn block_size(&self) -> usize;
+
+    /// Whether the stream is PKCS#7 padded to a multiple of the block size
+    fn padded(&self) -> bool;
+}
+
+/// A mode which can encrypt a stream
+pub trait EncryptMode: Mode {
+    /// Encrypt `data` in place, `data` is always a multiple of the block size. Successive calls
+    /// continue the stream.
+    fn encrypt_blocks(&mut self, data: &mut [u8]);
+}
+
+/// A mode which can decrypt a stream
+pub trait DecryptMode: Mode {
+    /// Decrypt `data` in place, `data` is always a multiple of the block size. Successive calls
+    /// continue the stream.
+    fn decrypt_blocks(&mut self, data: &mut [u8]);
+}
+
+/// ECB mode with PKCS#7 padding
+#[derive(Clone)]
+pub struct Ecb<C = Aes> {
+    cipher: C,
+}
+
+impl Ecb<Aes> {
+    /// ECB mode with AES, the AES variant is chosen by the length of `key`
+    pub fn new(key: impl AsRef<[u8]>) -> Result<Self, CipherError> {
+        Ok(Self::with_cipher(Aes::new_checked(key.as_ref())?))
+    }
+}
+
+impl<C: BlockSizeUser> Ecb<C> {
+    /// ECB mode with any block cipher
+    pub fn with_cipher(cipher: C) -> Self {
+        Self { cipher }
+    }
+}
+
+impl<C: BlockSizeUser> Mode for Ecb<C> {
+    fn block_size(&self) -> usize {
+        C::block_size()
+    }
+
+    fn padded(&self) -> bool {
+        true
+    }
+}
+
+impl<C: BlockEncrypt> EncryptMode for Ecb<C> {
+    fn encrypt_blocks(&mut self, data: &mut [u8]) {
+        ecb::encrypt_in_place_with(&self.cipher, data).expect(WHOLE_BLOCKS);
+    }
+}
+
+impl<C: BlockDecrypt> DecryptMode for Ecb<C> {
+    fn decrypt_blocks(&mut self, data: &mut [u8]) {
+        ecb::ecb_decrypt_in_place_with(&self.cipher, data).expect(WHOLE_BLOCKS);
+    }
+}
+
+/// CBC mode with PKCS#7 padding, the IV is updated as the stream is processed
+#[derive(Clone)]
+pub struct Cbc<C: BlockSizeUser = Aes> {
+    cipher: C,
+    iv: Block<C>,
+}
+
+impl Cbc<Aes> {
+    /// CBC mode with AES, the AES variant is chosen by the length of `key`
+    pub fn new(key: impl AsRef<[u8]>, iv: impl AsRef<[u8]>) -> Result<Self, CipherError> {
+        Self::with_cipher(Aes::new_checked(key.as_ref())?, iv)
+    }
+}
+
+impl<C: BlockSizeUser> Cbc<C> {
+    /// CBC mode with any block cipher
+    pub fn with_cipher(cipher: C, iv: impl AsRef<[u8]>) -> Result<Self, CipherError> {
+        let iv = iv.as_ref();
+        check_iv::<C>(iv)?;
+        Ok(Self {
+            cipher,
+            iv: Block::<C>::clone_from_slice(iv),
+        })
+    }
+}
+
+impl<C: BlockSizeUser> Mode for Cbc<C> {
+    fn block_size(&self) -> usize {
+        C::block_size()
+    }
+
+    fn padded(&self) -> bool {
+        true
+    }
+}
+
+impl<C: BlockEncrypt> EncryptMode for Cbc<C> {
+    fn encrypt_blocks(&mut self, data: &mut [u8]) {
+        cbc::encrypt_in_place_with(&self.cipher, &self.iv, data).expect(WHOLE_BLOCKS);
+        if let Some(last) = data.rchunks_exact(C::block_size()).next() {
+            self.iv.copy_from_slice(last);
+        }
+    }
+}
+
+impl<C: BlockDecrypt> DecryptMode for Cbc<C> {
+    fn decrypt_blocks(&mut self, data: &mut [u8]) {
+        let next_iv = data
+            .rchunks_exact(C::block_size())
+            .next()
+            .map(Block::<C>::clone_from_slice);
+        cbc::cbc_decrypt_in_place_with(&self.cipher, &self.iv, data).expect(WHOLE_BLOCKS);
+        if let Some(next_iv) = next_iv {
+            self.iv = next_iv;
+        }
+    }
+}
+
+/// Repeating key XOR, single byte XOR is a one byte key
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RepeatingXor {
+    key: Vec<u8>,
+    position: usize,
+}
+
+impl RepeatingXor {
+    pub fn new(key: impl AsRef<[u8]>) -> Self {
+        let key = key.as_ref().to_vec();
+        assert!(!key.is_empty(), "the key must be at least one byte");
+        Self { key, position: 0 }
+    }
+
+    fn apply(&mut self, data: &mut [u8]) {
+        for byte in data {
+            *byte ^= self.key[self.position];
+            self.position = (self.position + 1) % self.key.len();
+        }
+    }
+}
+
+impl Mode for RepeatingXor {
+    fn block_size(&self) -> usize {
+        1
+    }
+
+    fn padded(&self) -> bool {
+        false
+    }
+}
+
+impl EncryptMode for RepeatingXor {
+    fn encrypt_blocks(&mut self, data: &mut [u8]) {
+        self.apply(data)
+    }
+}
+
+impl DecryptMode for RepeatingXor {
+    fn decrypt_blocks(&mut self, data: &mut [u8]) {
+        self.apply(data)
+    }
+}
+
+/// Encrypts everything written to it before passing it on to the inner writer.
+///
+/// Any partial block is held until more is written, [`finish`](EncryptWriter::finish) must be
+/// called to pad and write the final block.
+///
+/// The mode's chaining state has moved on by the time the inner writer fails, so after an error
+/// from the inner writer every later write fails too rather than writing a corrupt stream.
+pub struct EncryptWriter<W: Write, M: EncryptMode> {
+    inner: W,
+    mode: M,
+    buffer: Vec<u8>,
+    failed: bool,
+}
+
+impl<W: Write, M: EncryptMode> EncryptWriter<W, M> {
+    pub fn new(inner: W, mode: M) -> Self {
+        Self {
+            inner,
+            mode,
+            buffer: vec![],
+            failed: false,
+        }
+    }
+
+    /// Pad and write the final block, returning the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.failed {
+            return Err(write_failed());
+        }
+        let mut last = std::mem::take(&mut self.buffer);
+        if self.mode.padded() {
+            last = pkcs::pad(last, self.mode.block_size() as u8);
+        }
+        self.mode.encrypt_blocks(&mut last);
+        self.inner.write_all(&last)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write, M: EncryptMode> Write for EncryptWriter<W, M> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.failed {
+            return Err(write_failed());
+        }
+        self.buffer.extend_from_slice(buf);
+        let whole = self.buffer.len() - self.buffer.len() % self.mode.block_size();
+        // Only cleared once the encrypted blocks are all written
+        self.failed = true;
+        self.mode.encrypt_blocks(&mut self.buffer[..whole]);
+        self.inner.write_all(&self.buffer[..whole])?;
+        self.failed = false;
+        self.buffer.drain(..whole);
+        Ok(buf.len())
+    }
+
+    /// Flushes the inner writer, a partial block is not written until more data arrives or the
+    /// writer is finished
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn write_failed() -> io::Error {
+    io::Error::other("an earlier write to the inner writer failed")
+}
+
+/// Decrypts everything read from the inner reader.
+///
+/// For padded modes the last block is held back until the end of the inner reader so that the
+/// padding can be removed. Truncated ciphertext and invalid padding are reported as
+/// [`io::ErrorKind::InvalidData`] wrapping a [`CipherError`], on this and every later read.
+pub struct DecryptReader<R: Read, M: DecryptMode> {
+    inner: R,
+    mode: M,
+    pending: Vec<u8>,
+    ready: Vec<u8>,
+    position: usize,
+    total: usize,
+    finished: bool,
+    error: Option<CipherError>,
+}
+
+impl<R: Read, M: DecryptMode> DecryptReader<R, M> {
+    pub fn new(inner: R, mode: M) -> Self {
+        Self {
+            inner,
+            mode,
+            pending: vec![],
+            ready: vec![],
+            position: 0,
+            total: 0,
+            finished: false,
+            error: None,
+        }
+    }
+
+    /// The inner reader
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Read from the inner reader until there is plaintext ready or the stream has ended
+    fn fill(&mut self) -> io::Result<()> {
+        if let Some(error) = &self.error {
+            return Err(invalid_data(error.clone()));
+        }
+        let block_size = self.mode.block_size();
+        while self.position == self.ready.len() && !self.finished {
+            let start = self.pending.len();
+            self.pending.resize(start + CHUNK_SIZE, 0);
+            let read = self.inner.read(&mut self.pending[start..]);
+            self.pending.truncate(start + *read.as_ref().unwrap_or(&0));
+            let read = match read {
+                Ok(0) => {
+                    self.finished = true;
+                    return self.finish_stream().map_err(|error| {
+                        self.error = Some(error.clone());
+                        invalid_data(error)
+                    });
+                }
+                Ok(read) => read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            self.total += read;
+
+            // Hold back the last whole block, it might be the padding
+            let mut whole = self.pending.len() - self.pending.len() % block_size;
+            if self.mode.padded() && whole == self.pending.len() {
+                whole = whole.saturating_sub(block_size);
+            }
+            self.mode.decrypt_blocks(&mut self.pending[..whole]);
+            self.ready = self.pending.drain(..whole).collect();
+            self.position = 0;
+        }
+        Ok(())
+    }
+
+    fn finish_stream(&mut self) -> Result<(), CipherError> {
+        let block_size = self.mode.block_size();
+        let mut last = std::mem::take(&mut self.pending);
+        if !last.len().is_multiple_of(block_size) || (self.mode.padded() && last.is_empty()) {
+            return Err(CipherError::NotBlockAligned {
+                length: self.total,
+                block_size,
+            });
+        }
+        self.mode.decrypt_blocks(&mut last);
+        if self.mode.padded() {
+            last = pkcs::unpad(last, block_size as u8)?;
+        }
+        self.ready = last;
+        self.position = 0;
+        Ok(())
+    }
+}
+
+fn invalid_data(error: CipherError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+impl<R: Read, M: DecryptMode> Read for DecryptReader<R, M> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.fill()?;
+        let available = &self.ready[self.position..];
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::Toy;
+    use crate::pkcs::PaddingError;
+    use crate::xor;
+    use aes::cipher::KeyInit;
+    use yare::parameterized;
+
+    const KEY: &[u8; 16] = b"YELLOW SUBMARINE";
+    const IV: &[u8; 16] = b"an initial value";
+
+    /// Gives out at most `limit` bytes per read
+    struct Trickle<'a> {
+        data: &'a [u8],
+        limit: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let count = self.data.len().min(buf.len()).min(self.limit);
+            buf[..count].copy_from_slice(&self.data[..count]);
+            self.data = &self.data[count..];
+            Ok(count)
+        }
+    }
+
+    fn message(length: usize) -> Vec<u8> {
+        (0..length).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    fn encrypt_in_pieces(mode: impl EncryptMode, data: &[u8], piece: usize) -> Vec<u8> {
+        let mut writer = EncryptWriter::new(vec![], mode);
+        for chunk in data.chunks(piece) {
+            writer.write_all(chunk).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn decrypt_trickle(mode: impl DecryptMode, data: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+        let mut plaintext = vec![];
+        DecryptReader::new(Trickle { data, limit }, mode).read_to_end(&mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    #[parameterized(
+    empty = { 0, 1 },
+    one_byte_writes = { 33, 1 },
+    partial_block = { 40, 7 },
+    whole_blocks = { 64, 16 },
+    many_chunks = { CHUNK_SIZE * 3 + 5, 1000 },
+    )]
+    fn ecb_matches_slices(length: usize, piece: usize) {
+        let raw = message(length);
+        let expected = ecb::encrypt_padded(KEY, &raw).unwrap();
+        let encrypted = encrypt_in_pieces(Ecb::new(KEY).unwrap(), &raw, piece);
+        assert_eq!(encrypted, expected);
+        let decrypted = decrypt_trickle(Ecb::new(KEY).unwrap(), &encrypted, piece).unwrap();
+        assert_eq!(decrypted, raw);
+    }
+
+    #[parameterized(
+    empty = { 0, 1 },
+    one_byte_writes = { 33, 1 },
+    partial_block = { 40, 7 },
+    whole_blocks = { 64, 16 },
+    many_chunks = { CHUNK_SIZE * 3 + 5, 1000 },
+    )]
+    fn cbc_matches_slices(length: usize, piece: usize) {
+        let raw = message(length);
+        let expected = cbc::encrypt_padded(KEY, IV, &raw).unwrap();
+        let encrypted = encrypt_in_pieces(Cbc::new(KEY, IV).unwrap(), &raw, piece);
+        assert_eq!(encrypted, expected);
+        let decrypted = decrypt_trickle(Cbc::new(KEY, IV).unwrap(), &encrypted, piece).unwrap();
+        assert_eq!(decrypted, raw);
+    }
+
+    #[test]
+    fn repeating_xor_matches_slices() {
+        let raw = message(1000);
+        let expected = <&[u8]>::from(&xor::xor_encrypt(&raw, b"ICE")).to_vec();
+        let encrypted = encrypt_in_pieces(RepeatingXor::new(b"ICE"), &raw, 7);
+        assert_eq!(encrypted, expected);
+        let decrypted = decrypt_trickle(RepeatingXor::new(b"ICE"), &encrypted, 5).unwrap();
+        assert_eq!(decrypted, raw);
+    }
+
+    #[test]
+    fn toy_cipher() {
+        let toy = Toy::new_from_slice(b"toy key!").unwrap();
+        let raw = b"eight byte blocks for the toy";
+        let encrypted =
+            encrypt_in_pieces(Cbc::with_cipher(toy.clone(), b"an iv!!!").unwrap(), raw, 3);
+        assert_eq!(
+            encrypted,
+            cbc::encrypt_padded_with(&toy, b"an iv!!!", raw).unwrap()
+        );
+        let mode = Cbc::with_cipher(toy, b"an iv!!!").unwrap();
+        assert_eq!(decrypt_trickle(mode, &encrypted, 3).unwrap(), raw);
+    }
+
+    #[test]
+    fn io_copy() {
+        let raw = message(100_000);
+        let mut writer = EncryptWriter::new(vec![], Cbc::new(KEY, IV).unwrap());
+        io::copy(&mut raw.as_slice(), &mut writer).unwrap();
+        let encrypted = writer.finish().unwrap();
+
+        let mut reader = DecryptReader::new(encrypted.as_slice(), Cbc::new(KEY, IV).unwrap());
+        let mut decrypted = vec![];
+        io::copy(&mut reader, &mut decrypted).unwrap();
+        assert_eq!(decrypted, raw);
+    }
+
+    fn cipher_error(error: io::Error) -> CipherError {
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        error
+            .into_inner()
+            .unwrap()
+            .downcast::<CipherError>()
+            .map(|error| *error)
+            .unwrap()
+    }
+
+    #[test]
+    fn bad_padding() {
+        let encrypted = ecb::encrypt(KEY, b"ends in a five\x05\x05").unwrap();
+        let error = decrypt_trickle(Ecb::new(KEY).unwrap(), &encrypted, 100).unwrap_err();
+        assert_eq!(
+            cipher_error(error),
+            CipherError::Padding(PaddingError::Inconsistent(5))
+        );
+    }
+
+    #[test]
+    fn errors_are_repeated() {
+        let encrypted = ecb::encrypt_padded(KEY, message(30)).unwrap();
+        let mut reader = DecryptReader::new(&encrypted[..20], Ecb::new(KEY).unwrap());
+        let expected = CipherError::NotBlockAligned {
+            length: 20,
+            block_size: 16,
+        };
+        let mut plaintext = vec![];
+        let error = reader.read_to_end(&mut plaintext).unwrap_err();
+        assert_eq!(cipher_error(error), expected);
+        assert_eq!(plaintext.len(), 16);
+        let error = reader.read(&mut [0; 16]).unwrap_err();
+        assert_eq!(cipher_error(error), expected);
+    }
+
+    /// Accepts `limit` bytes and then fails every write
+    struct Full {
+        written: Vec<u8>,
+        limit: usize,
+    }
+
+    impl Write for Full {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let count = buf.len().min(self.limit - self.written.len());
+            if count == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            self.written.extend_from_slice(&buf[..count]);
+            Ok(count)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn failed_write_is_not_retried() {
+        let inner = Full {
+            written: vec![],
+            limit: 16,
+        };
+        let mut writer = EncryptWriter::new(inner, Cbc::new(KEY, IV).unwrap());
+        assert!(writer.write(&message(32)).is_err());
+        assert!(writer.write(&message(32)).is_err());
+        assert!(writer.finish().is_err());
+    }
+
+    #[parameterized(
+    truncated = { 20 },
+    empty = { 0 },
+    )]
+    fn not_block_aligned(length: usize) {
+        let encrypted = ecb::encrypt_padded(KEY, message(30)).unwrap();
+        let error = decrypt_trickle(Ecb::new(KEY).unwrap(), &encrypted[..length], 100).unwrap_err();
+        assert_eq!(
+            cipher_error(error),
+            CipherError::NotBlockAligned {
+                length,
+                block_size: 16
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_iv_length() {
+        assert!(matches!(
+            Cbc::new(KEY, [0; 8]),
+            Err(CipherError::InvalidIvLength {
+                expected: 16,
+                actual: 8
+            })
+        ));
+    }
+}